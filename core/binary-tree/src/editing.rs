@@ -0,0 +1,235 @@
+//! Operations that restructure a `Tree` by moving whole
+//! subtrees around, rather than adding or removing single
+//! nodes.
+
+use std::collections::HashMap;
+use crate::{traversal::Order, *};
+
+impl<T> Tree<T> {
+    /// Which side of its parent a node is on. `None` for the root.
+    pub fn direction_of(&self, id: NodeId) -> Option<Direction> {
+        let parent = self.node(self.parent(id)?)?;
+        match parent.left == Some(id) {
+            true => Some(Direction::Left),
+            false => Some(Direction::Right),
+        }
+    }
+
+    /// The ids from `id` up to and including the root. Empty if
+    /// `id` is not in the tree.
+    pub fn path_to_root(&self, id: NodeId) -> Vec<NodeId> {
+        let mut path = vec![];
+        let mut current = Some(id).filter(|id| self.contains(*id));
+        while let Some(id) = current {
+            path.push(id);
+            current = self.parent(id);
+        }
+        path
+    }
+
+    /// Detach the child of `id` in a certain direction and return
+    /// it as a tree of its own.
+    pub fn take_child(&mut self, id: NodeId, direction: Direction) -> Option<Tree<T>> {
+        let child = self.child(id, direction)?;
+        self.unlink(child);
+        let mut subtree = Tree::new();
+        let root = self.move_subtree(child, &mut subtree, None);
+        subtree.root = Some(root);
+        Some(subtree)
+    }
+
+    /// Attach every node of `subtree` as the child of `id` in a
+    /// certain direction, provided that the node does not have a
+    /// child in that direction already. Returns the new id of the
+    /// subtree's root.
+    pub fn graft(&mut self, id: NodeId, direction: Direction, mut subtree: Tree<T>) -> Result<NodeId, TreeError> {
+        match self.node(id) {
+            None => return Err(TreeError::NoSuchNode { id }),
+            Some(node) if node.child(direction).is_some() => {
+                return Err(TreeError::ChildOccupied { direction })
+            }
+            Some(_) => (),
+        }
+        match subtree.root {
+            None => Err(TreeError::EmptyTree),
+            Some(root) => {
+                subtree.unlink(root);
+                Ok(subtree.move_subtree(root, self, Some((id, direction))))
+            }
+        }
+    }
+
+    /// Swap the child of `id` in a certain direction for `subtree`,
+    /// returning the subtree that was there before, if any.
+    pub fn replace_child(
+        &mut self,
+        id: NodeId,
+        direction: Direction,
+        subtree: Tree<T>,
+    ) -> Result<Option<Tree<T>>, TreeError> {
+        if !self.contains(id) {
+            return Err(TreeError::NoSuchNode { id });
+        }
+        if subtree.is_empty() {
+            return Err(TreeError::EmptyTree);
+        }
+        let old = self.take_child(id, direction);
+        self.graft(id, direction, subtree)?;
+        Ok(old)
+    }
+
+    /// Rotate the subtree at `id` to the left, so that its right
+    /// child takes its place. Returns the id of the new subtree root.
+    pub fn rotate_left(&mut self, id: NodeId) -> Result<NodeId, TreeError> {
+        self.rotate(id, Direction::Right)
+    }
+
+    /// Rotate the subtree at `id` to the right, so that its left
+    /// child takes its place. Returns the id of the new subtree root.
+    pub fn rotate_right(&mut self, id: NodeId) -> Result<NodeId, TreeError> {
+        self.rotate(id, Direction::Left)
+    }
+
+    /// Lift the child of `id` on side `up` into the place of `id`,
+    /// with `id` becoming its child on the other side.
+    fn rotate(&mut self, id: NodeId, up: Direction) -> Result<NodeId, TreeError> {
+        if !self.contains(id) {
+            return Err(TreeError::NoSuchNode { id });
+        }
+        let down = up.opposite();
+        let pivot = match self.child(id, up) {
+            Some(pivot) => pivot,
+            None => return Err(TreeError::NoSuchChild { direction: up }),
+        };
+
+        // The pivot's inner child moves across to `id`
+        let inner = self.child(pivot, down);
+        *self.node_mut(id).unwrap().child_mut(up) = inner;
+        if let Some(inner) = inner {
+            self.node_mut(inner).unwrap().parent = Some(id);
+        }
+
+        // The pivot takes the place of `id` under its parent
+        let parent = self.parent(id);
+        match (parent, self.direction_of(id)) {
+            (Some(parent), Some(direction)) => {
+                *self.node_mut(parent).unwrap().child_mut(direction) = Some(pivot)
+            }
+            _ => self.root = Some(pivot),
+        }
+        self.node_mut(pivot).unwrap().parent = parent;
+
+        // Then `id` hangs below the pivot
+        *self.node_mut(pivot).unwrap().child_mut(down) = Some(id);
+        self.node_mut(id).unwrap().parent = Some(pivot);
+        Ok(pivot)
+    }
+
+    /// Move an unlinked subtree into `dest`, attaching its root below
+    /// `attach` if given. Returns the id of the root in `dest`.
+    fn move_subtree(&mut self, id: NodeId, dest: &mut Tree<T>, attach: Option<(NodeId, Direction)>) -> NodeId {
+        let visits: Vec<(NodeId, Option<Direction>)> = self
+            .traverse_from(id, Order::PreOrder)
+            .map(|visit| (visit.id, visit.path.last().copied()))
+            .collect();
+
+        // Parents come before children in pre-order, so they have
+        // always been moved by the time we get to their children
+        let mut moved: HashMap<NodeId, NodeId> = HashMap::new();
+        for (old, direction) in visits {
+            let node = self.free_slot(old);
+            let link = match node.parent {
+                Some(parent) => Some((moved[&parent], direction.unwrap())),
+                None => attach,
+            };
+            let new = dest.alloc(Node::new(node.value, link.map(|(parent, _)| parent)));
+            if let Some((parent, direction)) = link {
+                *dest.node_mut(parent).unwrap().child_mut(direction) = Some(new);
+            }
+            moved.insert(old, new);
+        }
+        moved[&id]
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use crate::*;
+    use crate::traversal::tests::init;
+
+    fn in_order(tree: &Tree<i32>) -> Vec<i32> {
+        tree.in_order().map(|visit| *visit.value).collect()
+    }
+
+    #[test]
+    fn path_to_root() {
+        let tree = init();
+        let five = tree.pre_order().find(|visit| *visit.value == 5).unwrap().id;
+        let path: Vec<i32> = tree.path_to_root(five).into_iter().map(|id| tree[id]).collect();
+        assert!(path == vec![5, 2, 1]);
+        assert!(tree.direction_of(five) == Some(Direction::Right));
+        assert!(tree.direction_of(tree.root().unwrap()).is_none());
+    }
+
+    #[test]
+    fn take_child() {
+        let mut tree = init();
+        let root = tree.root().unwrap();
+        let taken = tree.take_child(root, Direction::Left).unwrap();
+
+        assert!(in_order(&taken) == vec![4, 2, 5]);
+        assert!(taken.parent(taken.root().unwrap()).is_none());
+        assert!(in_order(&tree) == vec![1, 3, 6]);
+        assert!(tree.len() == 3);
+        assert!(tree.take_child(root, Direction::Left).is_none());
+    }
+
+    #[test]
+    fn graft() {
+        let mut tree = Tree::with_root(1);
+        let root = tree.root().unwrap();
+        let mut subtree = Tree::with_root(2);
+        let sub_root = subtree.root().unwrap();
+        subtree.create_child(sub_root, 3, Direction::Left).unwrap();
+
+        let grafted = tree.graft(root, Direction::Right, subtree).unwrap();
+        assert!(tree[grafted] == 2);
+        assert!(tree.parent(grafted) == Some(root));
+        assert!(in_order(&tree) == vec![1, 3, 2]);
+        assert!(
+            tree.graft(root, Direction::Right, Tree::with_root(4))
+                == Err(TreeError::ChildOccupied { direction: Direction::Right })
+        );
+        assert!(tree.graft(root, Direction::Left, Tree::new()) == Err(TreeError::EmptyTree));
+    }
+
+    #[test]
+    fn replace_child() {
+        let mut tree = init();
+        let root = tree.root().unwrap();
+        let old = tree.replace_child(root, Direction::Right, Tree::with_root(7)).unwrap();
+        assert!(in_order(&old.unwrap()) == vec![3, 6]);
+        assert!(in_order(&tree) == vec![4, 2, 5, 1, 7]);
+    }
+
+    #[test]
+    fn rotations() {
+        let mut tree = init();
+        let root = tree.root().unwrap();
+
+        let new_root = tree.rotate_right(root).unwrap();
+        assert!(tree.root() == Some(new_root));
+        assert!(tree[new_root] == 2);
+        assert!(in_order(&tree) == vec![4, 2, 5, 1, 3, 6]);
+        assert!(tree.parent(root) == Some(new_root));
+
+        let back = tree.rotate_left(new_root).unwrap();
+        assert!(back == root);
+        assert!(tree == init());
+
+        let four = tree.pre_order().find(|visit| *visit.value == 4).unwrap().id;
+        assert!(tree.rotate_left(four) == Err(TreeError::NoSuchChild { direction: Direction::Right }));
+        tree.remove(four);
+        assert!(tree.rotate_right(four) == Err(TreeError::NoSuchNode { id: four }));
+    }
+}
@@ -0,0 +1,39 @@
+//! The error type for tree operations that can fail.
+
+use std::{error::Error, fmt};
+use crate::{Direction, NodeId};
+
+/// The reasons that an operation on a `Tree` can fail.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TreeError {
+    /// The node already has a child in that direction.
+    ChildOccupied { direction: Direction },
+    /// The tree already has a root.
+    RootOccupied,
+    /// There is no node with that id in the tree.
+    NoSuchNode { id: NodeId },
+    /// The node has no child in that direction.
+    NoSuchChild { direction: Direction },
+    /// The operation needed a tree with at least one node.
+    EmptyTree,
+    /// A serialised tree has a link to or from this node that
+    /// does not agree with the rest of the tree.
+    BrokenLink { id: NodeId },
+}
+
+impl fmt::Display for TreeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TreeError::ChildOccupied { direction } => {
+                write!(f, "Failed to override occupied {direction:?} child.")
+            }
+            TreeError::RootOccupied => write!(f, "Failed to override occupied root."),
+            TreeError::NoSuchNode { id } => write!(f, "No node with id {} in the tree.", id.index()),
+            TreeError::NoSuchChild { direction } => write!(f, "Node has no {direction:?} child."),
+            TreeError::EmptyTree => write!(f, "The tree is empty."),
+            TreeError::BrokenLink { id } => write!(f, "Node {} has a broken link.", id.index()),
+        }
+    }
+}
+
+impl Error for TreeError {}
@@ -0,0 +1,135 @@
+//! Rendering of a `Tree` as text for debugging, either as a
+//! Graphviz DOT graph or as an indented ASCII diagram.
+
+use std::fmt::Write;
+use crate::*;
+
+impl<T> Tree<T> {
+    /// Render the tree as a Graphviz DOT digraph, using `label` to
+    /// describe each node. Edges are labelled L or R.
+    pub fn to_dot<F>(&self, mut label: F) -> String
+    where
+        F: FnMut(&T) -> String,
+    {
+        let mut dot = String::from("digraph tree {\n");
+        for visit in self.pre_order() {
+            let id = visit.id.index();
+            let _ = writeln!(dot, "    n{id} [label=\"{}\"];", escape(&label(visit.value)));
+            for (direction, name) in [(Direction::Left, "L"), (Direction::Right, "R")] {
+                if let Some(child) = self.child(visit.id, direction) {
+                    let _ = writeln!(dot, "    n{id} -> n{} [label=\"{name}\"];", child.index());
+                }
+            }
+        }
+        dot.push_str("}\n");
+        dot
+    }
+
+    /// Render the tree as an indented ASCII diagram, using `label`
+    /// to describe each node. Each child is prefixed with L or R.
+    pub fn to_ascii<F>(&self, mut label: F) -> String
+    where
+        F: FnMut(&T) -> String,
+    {
+        let mut ascii = String::new();
+        if let Some(root) = self.root {
+            let _ = writeln!(ascii, "{}", label(&self[root]));
+            self.ascii_children(root, "", &mut label, &mut ascii);
+        }
+        ascii
+    }
+
+    /// Write every node below `id` as lines of the diagram, keeping
+    /// the nodes still to be written on a stack rather than
+    /// recursing, so that deep trees do not overflow the call stack.
+    fn ascii_children<F>(&self, id: NodeId, prefix: &str, label: &mut F, ascii: &mut String)
+    where
+        F: FnMut(&T) -> String,
+    {
+        let mut pending = vec![];
+        self.push_ascii_children(id, prefix, &mut pending);
+        while let Some((name, child, prefix, last)) = pending.pop() {
+            let (branch, indent) = match last {
+                true => ("└── ", "    "),
+                false => ("├── ", "│   "),
+            };
+            let _ = writeln!(ascii, "{prefix}{branch}{name}: {}", label(&self[child]));
+            self.push_ascii_children(child, &format!("{prefix}{indent}"), &mut pending);
+        }
+    }
+
+    /// Push the children of `id` so that the left one is popped first,
+    /// along with their prefix and whether they are the last child.
+    fn push_ascii_children(&self, id: NodeId, prefix: &str, pending: &mut Vec<(&'static str, NodeId, String, bool)>) {
+        let children: Vec<(&str, NodeId)> = [(Direction::Left, "L"), (Direction::Right, "R")]
+            .into_iter()
+            .filter_map(|(direction, name)| Some((name, self.child(id, direction)?)))
+            .collect();
+        for (i, (name, child)) in children.iter().enumerate().rev() {
+            pending.push((name, *child, prefix.to_owned(), i == children.len() - 1));
+        }
+    }
+}
+
+/// Escape a label so that it can go between quotes in DOT.
+fn escape(label: &str) -> String {
+    label
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+#[cfg(test)]
+pub mod tests {
+    use crate::*;
+    use crate::traversal::tests::init;
+
+    #[test]
+    fn ascii() {
+        let tree = init();
+        let expected = "\
+1
+├── L: 2
+│   ├── L: 4
+│   └── R: 5
+└── R: 3
+    └── R: 6
+";
+        assert!(tree.to_ascii(|value| value.to_string()) == expected);
+    }
+
+    #[test]
+    fn degenerate() {
+        let mut tree = Tree::with_root(0);
+        let mut id = tree.root().unwrap();
+        for value in 1..=2000 {
+            id = tree.create_child(id, value, Direction::Right).unwrap();
+        }
+        let ascii = tree.to_ascii(|value| value.to_string());
+        assert!(ascii.lines().count() == 2001);
+        assert!(ascii.lines().last().unwrap() == format!("{}└── R: 2000", "    ".repeat(1999)));
+    }
+
+    #[test]
+    fn dot() {
+        let mut tree = Tree::with_root("a \"quoted\" label");
+        let root = tree.root().unwrap();
+        tree.create_child(root, "b", Direction::Right).unwrap();
+
+        let expected = "\
+digraph tree {
+    n0 [label=\"a \\\"quoted\\\" label\"];
+    n0 -> n1 [label=\"R\"];
+    n1 [label=\"b\"];
+}
+";
+        assert!(tree.to_dot(|value| value.to_string()) == expected);
+    }
+
+    #[test]
+    fn empty() {
+        let tree: Tree<i32> = Tree::new();
+        assert!(tree.to_ascii(|value| value.to_string()).is_empty());
+        assert!(tree.to_dot(|value| value.to_string()) == "digraph tree {\n}\n");
+    }
+}
@@ -1,88 +1,347 @@
-use anyhow::{anyhow, Result};
 use serde::{Deserialize, Serialize};
-use std::cell::RefCell;
 /// This crate provides a binary tree structure for
-/// this project.
-use std::rc::Rc;
+/// this project. Nodes are stored in an arena owned by
+/// the tree and refer to each other by index, so trees
+/// can be sent between threads and serialised without
+/// duplicating any nodes.
+use std::ops::{Index, IndexMut};
+
+pub mod editing;
+pub mod error;
+pub mod export;
+pub mod metrics;
+pub mod traversal;
+
+pub use error::TreeError;
 
 /// This is the enum for the direction of the nodes. It
 /// allows us to traverse using types that are named
 /// rather than using magic values.
-#[derive(Serialize, Deserialize)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub enum Direction {
     Left,
     Right,
 }
+impl Direction {
+    /// The other direction to this one.
+    pub fn opposite(self) -> Self {
+        match self {
+            Direction::Left => Direction::Right,
+            Direction::Right => Direction::Left,
+        }
+    }
+}
 
-/// Node reference type. Gets a new reference on a clone.
-pub type NodeRef<T> = Rc<RefCell<Node<T>>>;
+/// Index of a node inside of a `Tree`. Only valid for the
+/// tree that handed it out.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct NodeId(usize);
+impl NodeId {
+    /// The position of the node in the tree's arena.
+    pub fn index(self) -> usize {
+        self.0
+    }
+}
 
-/// The main node struct that allows for construction of
-/// binary trees. Always contains a value T, and can
-/// have 0-2 child nodes, as indicated by the Option<>
-/// type.
-#[derive(Debug, Clone, PartialEq, PartialOrd, Serialize, Deserialize)]
+/// A single node stored in a `Tree`. Always contains a
+/// value T, and can have 0-2 child nodes, as indicated
+/// by the Option<> type.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Node<T> {
     pub value: T,
-    pub left: Option<NodeRef<T>>,
-    pub right: Option<NodeRef<T>>,
+    parent: Option<NodeId>,
+    left: Option<NodeId>,
+    right: Option<NodeId>,
 }
 impl<T> Node<T> {
-    /// Create a single node node
-    pub fn new(value: T) -> Self {
+    /// Create a single node with no links
+    fn new(value: T, parent: Option<NodeId>) -> Self {
         Self {
             value,
+            parent,
             left: None,
             right: None,
         }
     }
 
-    /// Create a child node of self with a value in a certain direction, provided
-    /// that the node does not have a child in that direction already.
-    pub fn create_child(&mut self, value: T, direction: Direction) -> Result<()> {
+    /// The node above this one, if it is not the root.
+    pub fn parent(&self) -> Option<NodeId> {
+        self.parent
+    }
+
+    /// The left child of this node.
+    pub fn left(&self) -> Option<NodeId> {
+        self.left
+    }
+
+    /// The right child of this node.
+    pub fn right(&self) -> Option<NodeId> {
+        self.right
+    }
+
+    /// The child of this node in a certain direction.
+    pub fn child(&self, direction: Direction) -> Option<NodeId> {
         match direction {
-            Direction::Left => match self.left {
-                Some(_) => return Err(anyhow!("Failed to override occupied child.")),
-                None => {
-                    self.left = Some(Node::new(value).noderef());
-                    return Ok(());
-                }
-            },
-            Direction::Right => match self.right {
-                Some(_) => return Err(anyhow!("Failed to override occupied child.")),
-                None => {
-                    self.right = Some(Node::new(value).noderef());
-                    return Ok(());
-                }
-            },
-        };
+            Direction::Left => self.left,
+            Direction::Right => self.right,
+        }
     }
 
-    /// Returns whether the current node has a child node.
+    /// Returns whether the current node has no child nodes.
     pub fn is_leaf(&self) -> bool {
-        match (self.left.clone(), self.right.clone()) {
-            (None, None) => true,
-            _ => false,
-        }
+        self.left.is_none() && self.right.is_none()
     }
 
-    /// Get an optional reference to a child of self, dependent on direction.
-    pub fn get_child_reference(&self, direction: Direction) -> Option<NodeRef<T>> {
+    /// The ids of the children of this node that exist.
+    fn children(&self) -> impl Iterator<Item = NodeId> {
+        self.left.into_iter().chain(self.right)
+    }
+
+    fn child_mut(&mut self, direction: Direction) -> &mut Option<NodeId> {
         match direction {
-            Direction::Left => match &self.left {
-                Some(child) => Some(child.clone()),
-                None => None,
-            },
-            Direction::Right => match &self.right {
-                Some(child) => Some(child.clone()),
-                None => None,
-            },
+            Direction::Left => &mut self.left,
+            Direction::Right => &mut self.right,
+        }
+    }
+}
+
+/// The main tree struct. Owns every node in a flat arena,
+/// with removed slots being reused by later insertions.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(try_from = "RawTree<T>")]
+pub struct Tree<T> {
+    nodes: Vec<Option<Node<T>>>,
+    free: Vec<NodeId>,
+    root: Option<NodeId>,
+}
+
+/// A tree as it is read in, before its links have been checked.
+#[derive(Deserialize)]
+struct RawTree<T> {
+    nodes: Vec<Option<Node<T>>>,
+    free: Vec<NodeId>,
+    root: Option<NodeId>,
+}
+impl<T> TryFrom<RawTree<T>> for Tree<T> {
+    type Error = TreeError;
+
+    /// Every child must point back at its parent, every node must
+    /// hang from the root, and the free list must hold exactly the
+    /// empty slots, so that nothing later can index out of bounds.
+    fn try_from(raw: RawTree<T>) -> Result<Self, TreeError> {
+        let tree = Tree {
+            nodes: raw.nodes,
+            free: raw.free,
+            root: raw.root,
+        };
+
+        let mut free = vec![false; tree.nodes.len()];
+        for id in &tree.free {
+            if tree.contains(*id) || id.0 >= free.len() || free[id.0] {
+                return Err(TreeError::BrokenLink { id: *id });
+            }
+            free[id.0] = true;
+        }
+        for (index, node) in tree.nodes.iter().enumerate() {
+            let id = NodeId(index);
+            let Some(node) = node else {
+                match free[index] {
+                    true => continue,
+                    false => return Err(TreeError::BrokenLink { id }),
+                }
+            };
+            let children_agree = node.children().all(|child| tree.parent(child) == Some(id));
+            let parent_agrees = match node.parent {
+                Some(parent) => tree.node(parent).is_some_and(|parent| parent.children().any(|child| child == id)),
+                None => tree.root == Some(id),
+            };
+            let root_agrees = tree.root != Some(id) || node.parent.is_none();
+            if node.left.is_some() && node.left == node.right || !children_agree || !parent_agrees || !root_agrees {
+                return Err(TreeError::BrokenLink { id });
+            }
+        }
+
+        // With the links agreeing every node has one parent, so the
+        // only way to miss the root is to be part of a loop
+        let mut reached = 0;
+        let mut stack: Vec<NodeId> = tree.root.into_iter().collect();
+        while let Some(id) = stack.pop() {
+            reached += 1;
+            stack.extend(tree.node(id).ok_or(TreeError::BrokenLink { id })?.children());
+        }
+        match tree.nodes.iter().position(Option::is_some) {
+            Some(index) if reached != tree.len() => Err(TreeError::BrokenLink { id: NodeId(index) }),
+            _ => Ok(tree),
+        }
+    }
+}
+impl<T> Default for Tree<T> {
+    fn default() -> Self {
+        Self {
+            nodes: vec![],
+            free: vec![],
+            root: None,
+        }
+    }
+}
+impl<T> Tree<T> {
+    /// Create a tree with no nodes
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Create a tree with a single root node
+    pub fn with_root(value: T) -> Self {
+        let mut tree = Self::new();
+        tree.root = Some(tree.alloc(Node::new(value, None)));
+        tree
+    }
+
+    /// Give an empty tree a root node, provided that it does
+    /// not have one already.
+    pub fn insert_root(&mut self, value: T) -> Result<NodeId, TreeError> {
+        match self.root {
+            Some(_) => Err(TreeError::RootOccupied),
+            None => {
+                let id = self.alloc(Node::new(value, None));
+                self.root = Some(id);
+                Ok(id)
+            }
+        }
+    }
+
+    /// The root node of the tree, if it has one.
+    pub fn root(&self) -> Option<NodeId> {
+        self.root
+    }
+
+    /// The number of nodes in the tree.
+    pub fn len(&self) -> usize {
+        self.nodes.len() - self.free.len()
+    }
+
+    /// Returns whether the tree has no nodes.
+    pub fn is_empty(&self) -> bool {
+        self.root.is_none()
+    }
+
+    /// Returns whether `id` refers to a node in this tree.
+    pub fn contains(&self, id: NodeId) -> bool {
+        self.node(id).is_some()
+    }
+
+    /// Get the node with a certain id.
+    pub fn node(&self, id: NodeId) -> Option<&Node<T>> {
+        self.nodes.get(id.0).and_then(Option::as_ref)
+    }
+
+    /// Get the value of the node with a certain id.
+    pub fn get(&self, id: NodeId) -> Option<&T> {
+        self.node(id).map(|node| &node.value)
+    }
+
+    /// Get the value of the node with a certain id mutably.
+    pub fn get_mut(&mut self, id: NodeId) -> Option<&mut T> {
+        self.node_mut(id).map(|node| &mut node.value)
+    }
+
+    /// Get the id of a child of a node, dependent on direction.
+    pub fn child(&self, id: NodeId, direction: Direction) -> Option<NodeId> {
+        self.node(id).and_then(|node| node.child(direction))
+    }
+
+    /// Get the id of the parent of a node.
+    pub fn parent(&self, id: NodeId) -> Option<NodeId> {
+        self.node(id).and_then(Node::parent)
+    }
+
+    /// Returns whether the node has no child nodes.
+    pub fn is_leaf(&self, id: NodeId) -> bool {
+        self.node(id).is_some_and(Node::is_leaf)
+    }
+
+    /// Create a child node of `id` with a value in a certain direction,
+    /// provided that the node does not have a child in that direction
+    /// already. Returns the id of the new child.
+    pub fn create_child(&mut self, id: NodeId, value: T, direction: Direction) -> Result<NodeId, TreeError> {
+        match self.node(id) {
+            None => Err(TreeError::NoSuchNode { id }),
+            Some(node) if node.child(direction).is_some() => {
+                Err(TreeError::ChildOccupied { direction })
+            }
+            Some(_) => {
+                let child = self.alloc(Node::new(value, Some(id)));
+                *self.node_mut(id).unwrap().child_mut(direction) = Some(child);
+                Ok(child)
+            }
         }
     }
 
-    /// Get a clonable, interior-mutable reference to self.
-    pub fn noderef(self) -> NodeRef<T> {
-        Rc::new(RefCell::new(self))
+    /// Remove a node and everything below it from the tree,
+    /// returning the value of the removed node.
+    pub fn remove(&mut self, id: NodeId) -> Option<T> {
+        if !self.contains(id) {
+            return None;
+        }
+        self.unlink(id);
+
+        // Free the descendants first, then the node itself
+        let mut stack: Vec<NodeId> = self.node(id).unwrap().children().collect();
+        while let Some(next) = stack.pop() {
+            let node = self.free_slot(next);
+            stack.extend(node.children());
+        }
+        Some(self.free_slot(id).value)
+    }
+
+    /// Remove the link between a node and its parent, or make the
+    /// tree empty if it is the root.
+    fn unlink(&mut self, id: NodeId) {
+        match (self.parent(id), self.direction_of(id)) {
+            (Some(parent), Some(direction)) => {
+                *self.node_mut(parent).unwrap().child_mut(direction) = None
+            }
+            _ => self.root = None,
+        }
+        if let Some(node) = self.node_mut(id) {
+            node.parent = None;
+        }
+    }
+
+    fn node_mut(&mut self, id: NodeId) -> Option<&mut Node<T>> {
+        self.nodes.get_mut(id.0).and_then(Option::as_mut)
+    }
+
+    fn alloc(&mut self, node: Node<T>) -> NodeId {
+        match self.free.pop() {
+            Some(id) => {
+                self.nodes[id.0] = Some(node);
+                id
+            }
+            None => {
+                self.nodes.push(Some(node));
+                NodeId(self.nodes.len() - 1)
+            }
+        }
+    }
+
+    fn free_slot(&mut self, id: NodeId) -> Node<T> {
+        self.free.push(id);
+        self.nodes[id.0].take().unwrap()
+    }
+}
+
+impl<T> Index<NodeId> for Tree<T> {
+    type Output = T;
+
+    fn index(&self, id: NodeId) -> &T {
+        self.get(id).expect("No node with that id in the tree.")
+    }
+}
+
+impl<T> IndexMut<NodeId> for Tree<T> {
+    fn index_mut(&mut self, id: NodeId) -> &mut T {
+        self.get_mut(id).expect("No node with that id in the tree.")
     }
 }
 
@@ -91,48 +350,121 @@ pub mod tests {
     use super::*;
 
     #[test]
-    fn new_node() {
-        assert!(
-            Node::new(10)
-                == Node {
-                    value: 10,
-                    left: None,
-                    right: None
-                }
-        )
+    fn new_tree() {
+        let tree = Tree::with_root(10);
+        let root = tree.root().unwrap();
+        assert!(tree[root] == 10);
+        assert!(tree.len() == 1);
+        assert!(tree.is_leaf(root));
     }
 
     #[test]
-    fn noderef() {
-        assert!(Node::new(10).noderef() == Rc::new(RefCell::new(Node::new(10))))
+    fn insert_root() {
+        let mut tree = Tree::new();
+        assert!(tree.is_empty());
+        let root = tree.insert_root(10).unwrap();
+        assert!(tree.root() == Some(root));
+        assert!(tree.insert_root(20) == Err(TreeError::RootOccupied));
     }
 
     #[test]
     fn create_child() {
-        let mut a = Node::new(10);
-        a.create_child(20, Direction::Left).expect("");
-
-        let b = Node {
-            value: 10,
-            left: Some(
-                Node {
-                    value: 20,
-                    left: None,
-                    right: None,
-                }
-                .noderef(),
-            ),
-            right: None,
-        };
+        let mut tree = Tree::with_root(10);
+        let root = tree.root().unwrap();
+        let child = tree.create_child(root, 20, Direction::Left).expect("");
 
-        assert!(a == b)
+        assert!(tree.child(root, Direction::Left) == Some(child));
+        assert!(tree.child(root, Direction::Right).is_none());
+        assert!(tree.parent(child) == Some(root));
+        assert!(tree[child] == 20);
+        assert!(
+            tree.create_child(root, 30, Direction::Left)
+                == Err(TreeError::ChildOccupied { direction: Direction::Left })
+        );
+    }
+
+    #[test]
+    fn no_such_node() {
+        let mut tree = Tree::with_root(1);
+        let root = tree.root().unwrap();
+        let child = tree.create_child(root, 2, Direction::Left).unwrap();
+        tree.remove(child);
+        assert!(tree.create_child(child, 3, Direction::Left) == Err(TreeError::NoSuchNode { id: child }));
     }
 
     #[test]
     fn is_leaf() {
-        let mut root = Node::new(1);
-        root.create_child(2, Direction::Left).unwrap();
-        assert!(root.is_leaf() == false);
-        assert!(root.left.unwrap().borrow().is_leaf() == true);
+        let mut tree = Tree::with_root(1);
+        let root = tree.root().unwrap();
+        let child = tree.create_child(root, 2, Direction::Left).unwrap();
+        assert!(!tree.is_leaf(root));
+        assert!(tree.is_leaf(child));
+    }
+
+    #[test]
+    fn remove() {
+        let mut tree = Tree::with_root(1);
+        let root = tree.root().unwrap();
+        let left = tree.create_child(root, 2, Direction::Left).unwrap();
+        tree.create_child(left, 3, Direction::Right).unwrap();
+        tree.create_child(root, 4, Direction::Right).unwrap();
+
+        assert!(tree.remove(left) == Some(2));
+        assert!(tree.len() == 2);
+        assert!(tree.child(root, Direction::Left).is_none());
+        assert!(!tree.contains(left));
+
+        // The freed slots get reused
+        let reused = tree.create_child(root, 5, Direction::Left).unwrap();
+        assert!(reused.index() < 4);
+        assert!(tree.len() == 3);
+    }
+
+    #[test]
+    fn serde_round_trip() {
+        let mut tree = Tree::with_root(1);
+        let root = tree.root().unwrap();
+        tree.create_child(root, 2, Direction::Left).unwrap();
+        tree.create_child(root, 3, Direction::Right).unwrap();
+
+        let json = serde_json::to_string(&tree).unwrap();
+        let back: Tree<i32> = serde_json::from_str(&json).unwrap();
+        assert!(back == tree);
+    }
+
+    #[test]
+    fn serde_broken_links() {
+        let broken = [
+            // Child out of bounds
+            r#"{"nodes":[{"value":1,"parent":null,"left":5,"right":null}],"free":[],"root":0}"#,
+            // Child that does not point back
+            r#"{"nodes":[{"value":1,"parent":null,"left":1,"right":null},{"value":2,"parent":null,"left":null,"right":null}],"free":[],"root":0}"#,
+            // Root out of bounds
+            r#"{"nodes":[],"free":[],"root":3}"#,
+            // Empty slot missing from the free list
+            r#"{"nodes":[{"value":1,"parent":null,"left":null,"right":null},null],"free":[],"root":0}"#,
+            // Free list pointing at a live node
+            r#"{"nodes":[{"value":1,"parent":null,"left":null,"right":null}],"free":[0],"root":0}"#,
+            // Root with a parent, in a loop with it
+            r#"{"nodes":[{"value":1,"parent":1,"left":1,"right":null},{"value":2,"parent":0,"left":0,"right":null}],"free":[],"root":0}"#,
+            // Two nodes in a loop, away from the root
+            r#"{"nodes":[{"value":1,"parent":null,"left":null,"right":null},{"value":2,"parent":2,"left":2,"right":null},{"value":3,"parent":1,"left":1,"right":null}],"free":[],"root":0}"#,
+        ];
+        for json in broken {
+            assert!(serde_json::from_str::<Tree<i32>>(json).is_err());
+        }
+
+        let mut tree = Tree::with_root(1);
+        let root = tree.root().unwrap();
+        let child = tree.create_child(root, 2, Direction::Left).unwrap();
+        tree.remove(child);
+        let json = serde_json::to_string(&tree).unwrap();
+        assert!(serde_json::from_str::<Tree<i32>>(&json).unwrap() == tree);
+    }
+
+    #[test]
+    fn send_sync() {
+        fn assert_send_sync<S: Send + Sync>() {}
+        assert_send_sync::<Tree<Vec<u8>>>();
     }
 }
@@ -0,0 +1,146 @@
+//! Structural measurements of a `Tree`, and the `map` and
+//! `fold` combinators that they are built on.
+
+use crate::{traversal::Order, *};
+
+impl<T> Tree<T> {
+    /// Build a new tree with the same shape and the same node
+    /// ids, with every value passed through `f`.
+    pub fn map<U, F>(&self, mut f: F) -> Tree<U>
+    where
+        F: FnMut(&T) -> U,
+    {
+        let nodes = self
+            .nodes
+            .iter()
+            .map(|slot| {
+                slot.as_ref().map(|node| Node {
+                    value: f(&node.value),
+                    parent: node.parent,
+                    left: node.left,
+                    right: node.right,
+                })
+            })
+            .collect();
+
+        Tree {
+            nodes,
+            free: self.free.clone(),
+            root: self.root,
+        }
+    }
+
+    /// Reduce the whole tree from the leaves up. `f` is given the
+    /// value of a node and the results for its left and right
+    /// subtrees. Returns `None` for an empty tree.
+    pub fn fold<B, F>(&self, f: F) -> Option<B>
+    where
+        F: FnMut(&T, Option<B>, Option<B>) -> B,
+    {
+        self.fold_from(self.root?, f)
+    }
+
+    /// Reduce the subtree below `id` from the leaves up, in the
+    /// same way as `fold`.
+    pub fn fold_from<B, F>(&self, id: NodeId, mut f: F) -> Option<B>
+    where
+        F: FnMut(&T, Option<B>, Option<B>) -> B,
+    {
+        // Post-order means both children are done before their parent
+        let mut results: Vec<Option<B>> = self.nodes.iter().map(|_| None).collect();
+        for visit in self.traverse_from(id, Order::PostOrder) {
+            let node = self.node(visit.id).unwrap();
+            let left = node.left.and_then(|child| results[child.0].take());
+            let right = node.right.and_then(|child| results[child.0].take());
+            results[visit.id.0] = Some(f(visit.value, left, right));
+        }
+        results.get_mut(id.0).and_then(Option::take)
+    }
+
+    /// The number of nodes in the tree.
+    pub fn size(&self) -> usize {
+        self.len()
+    }
+
+    /// The number of nodes in the subtree below `id`, including itself.
+    pub fn subtree_size(&self, id: NodeId) -> usize {
+        self.fold_from(id, |_, left, right| 1 + left.unwrap_or(0) + right.unwrap_or(0))
+            .unwrap_or(0)
+    }
+
+    /// The number of nodes on the longest path from the root down
+    /// to a leaf. An empty tree has a height of 0 and a lone root
+    /// has a height of 1.
+    pub fn height(&self) -> usize {
+        self.root.map_or(0, |root| self.subtree_height(root))
+    }
+
+    /// The height of the subtree below `id`, measured the same way
+    /// as `height`.
+    pub fn subtree_height(&self, id: NodeId) -> usize {
+        self.fold_from(id, |_, left, right| 1 + left.unwrap_or(0).max(right.unwrap_or(0)))
+            .unwrap_or(0)
+    }
+
+    /// The number of nodes with no children.
+    pub fn leaf_count(&self) -> usize {
+        self.pre_order().filter(|visit| self.is_leaf(visit.id)).count()
+    }
+
+    /// The height of the left subtree of `id` minus the height of
+    /// its right subtree. Positive values mean it leans left.
+    pub fn balance_factor(&self, id: NodeId) -> isize {
+        let height = |direction| {
+            self.child(id, direction)
+                .map_or(0, |child| self.subtree_height(child)) as isize
+        };
+        height(Direction::Left) - height(Direction::Right)
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use crate::*;
+    use crate::traversal::tests::init;
+
+    #[test]
+    fn metrics() {
+        let tree = init();
+        let root = tree.root().unwrap();
+        let two = tree.child(root, Direction::Left).unwrap();
+        assert!(tree.size() == 6);
+        assert!(tree.subtree_size(two) == 3);
+        assert!(tree.height() == 3);
+        assert!(tree.subtree_height(two) == 2);
+        assert!(tree.leaf_count() == 3);
+        assert!(tree.balance_factor(root) == 0);
+        assert!(tree.balance_factor(tree.child(root, Direction::Right).unwrap()) == -1);
+    }
+
+    #[test]
+    fn empty_metrics() {
+        let tree: Tree<i32> = Tree::new();
+        assert!(tree.size() == 0);
+        assert!(tree.height() == 0);
+        assert!(tree.leaf_count() == 0);
+        assert!(tree.fold(|_, _, _| ()).is_none());
+    }
+
+    #[test]
+    fn map_keeps_shape() {
+        let tree = init();
+        let mapped = tree.map(|value| value.to_string());
+        for (before, after) in tree.pre_order().zip(mapped.pre_order()) {
+            assert!(before.id == after.id);
+            assert!(before.path == after.path);
+            assert!(before.value.to_string() == *after.value);
+        }
+    }
+
+    #[test]
+    fn fold_sum() {
+        let tree = init();
+        let sum = tree.fold(|value, left, right| value + left.unwrap_or(0) + right.unwrap_or(0));
+        assert!(sum == Some(21));
+    }
+}
@@ -0,0 +1,251 @@
+//! Borrowing iterators and a visitor for walking the nodes
+//! of a `Tree` in the usual orders.
+
+use std::collections::VecDeque;
+use std::ops::ControlFlow;
+use crate::*;
+
+/// The order that a traversal visits nodes in.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Order {
+    /// Node, then left subtree, then right subtree.
+    PreOrder,
+    /// Left subtree, then node, then right subtree.
+    InOrder,
+    /// Left subtree, then right subtree, then node.
+    PostOrder,
+    /// Breadth first, one depth at a time from left to right.
+    LevelOrder,
+}
+
+/// A single node yielded by a traversal.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Visit<'a, T> {
+    pub id: NodeId,
+    pub value: &'a T,
+    /// Number of edges between the node and the root.
+    pub depth: usize,
+    /// The directions taken from the root to reach the node.
+    pub path: Vec<Direction>,
+}
+
+/// A node waiting to be visited. `expanded` is set once its
+/// children have been queued, for the orders that visit a
+/// node after some of its children.
+struct Frame {
+    id: NodeId,
+    depth: usize,
+    path: Vec<Direction>,
+    expanded: bool,
+}
+
+/// Iterator over the nodes of a tree in a certain order.
+pub struct Traversal<'a, T> {
+    tree: &'a Tree<T>,
+    order: Order,
+    pending: VecDeque<Frame>,
+}
+impl<'a, T> Traversal<'a, T> {
+    /// Start a traversal from `start`, treating it as the root.
+    pub fn new(tree: &'a Tree<T>, start: Option<NodeId>, order: Order) -> Self {
+        let pending = start
+            .filter(|id| tree.contains(*id))
+            .map(|id| Frame {
+                id,
+                depth: 0,
+                path: vec![],
+                expanded: false,
+            })
+            .into_iter()
+            .collect();
+        Traversal {
+            tree,
+            order,
+            pending,
+        }
+    }
+
+    fn child_frame(&self, frame: &Frame, direction: Direction) -> Option<Frame> {
+        self.tree.child(frame.id, direction).map(|id| {
+            let mut path = frame.path.clone();
+            path.push(direction);
+            Frame {
+                id,
+                depth: frame.depth + 1,
+                path,
+                expanded: false,
+            }
+        })
+    }
+
+    fn visit(&self, frame: Frame) -> Visit<'a, T> {
+        Visit {
+            id: frame.id,
+            value: &self.tree[frame.id],
+            depth: frame.depth,
+            path: frame.path,
+        }
+    }
+}
+
+impl<'a, T> Iterator for Traversal<'a, T> {
+    type Item = Visit<'a, T>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            // Level order is a queue, the others are a stack
+            let frame = match self.order {
+                Order::LevelOrder => self.pending.pop_front()?,
+                _ => self.pending.pop_back()?,
+            };
+            let left = self.child_frame(&frame, Direction::Left);
+            let right = self.child_frame(&frame, Direction::Right);
+
+            match self.order {
+                Order::PreOrder => {
+                    self.pending.extend(right);
+                    self.pending.extend(left);
+                    return Some(self.visit(frame));
+                }
+                Order::LevelOrder => {
+                    self.pending.extend(left);
+                    self.pending.extend(right);
+                    return Some(self.visit(frame));
+                }
+                _ if frame.expanded => return Some(self.visit(frame)),
+                Order::InOrder => {
+                    self.pending.extend(right);
+                    self.pending.push_back(Frame { expanded: true, ..frame });
+                    self.pending.extend(left);
+                }
+                Order::PostOrder => {
+                    self.pending.push_back(Frame { expanded: true, ..frame });
+                    self.pending.extend(right);
+                    self.pending.extend(left);
+                }
+            }
+        }
+    }
+}
+
+impl<T> Tree<T> {
+    /// Iterate over the whole tree in a certain order.
+    pub fn traverse(&self, order: Order) -> Traversal<'_, T> {
+        Traversal::new(self, self.root(), order)
+    }
+
+    /// Iterate over the subtree below `id` in a certain order.
+    /// Depths and paths are relative to `id`.
+    pub fn traverse_from(&self, id: NodeId, order: Order) -> Traversal<'_, T> {
+        Traversal::new(self, Some(id), order)
+    }
+
+    /// Iterate over the tree in pre-order.
+    pub fn pre_order(&self) -> Traversal<'_, T> {
+        self.traverse(Order::PreOrder)
+    }
+
+    /// Iterate over the tree in in-order.
+    pub fn in_order(&self) -> Traversal<'_, T> {
+        self.traverse(Order::InOrder)
+    }
+
+    /// Iterate over the tree in post-order.
+    pub fn post_order(&self) -> Traversal<'_, T> {
+        self.traverse(Order::PostOrder)
+    }
+
+    /// Iterate over the tree in level-order.
+    pub fn level_order(&self) -> Traversal<'_, T> {
+        self.traverse(Order::LevelOrder)
+    }
+
+    /// Call `visitor` on each node in a certain order until it
+    /// returns `ControlFlow::Break`, and return the value it broke
+    /// with. Returns `None` if every node was visited.
+    pub fn visit<B, F>(&self, order: Order, mut visitor: F) -> Option<B>
+    where
+        F: FnMut(Visit<'_, T>) -> ControlFlow<B>,
+    {
+        for visit in self.traverse(order) {
+            if let ControlFlow::Break(value) = visitor(visit) {
+                return Some(value);
+            }
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use crate::traversal::*;
+
+    /// Builds the tree
+    ///       1
+    ///      / \
+    ///     2   3
+    ///    / \   \
+    ///   4   5   6
+    pub fn init() -> Tree<i32> {
+        let mut tree = Tree::with_root(1);
+        let root = tree.root().unwrap();
+        let two = tree.create_child(root, 2, Direction::Left).unwrap();
+        let three = tree.create_child(root, 3, Direction::Right).unwrap();
+        tree.create_child(two, 4, Direction::Left).unwrap();
+        tree.create_child(two, 5, Direction::Right).unwrap();
+        tree.create_child(three, 6, Direction::Right).unwrap();
+        tree
+    }
+
+    fn values(traversal: Traversal<'_, i32>) -> Vec<i32> {
+        traversal.map(|visit| *visit.value).collect()
+    }
+
+    #[test]
+    fn orders() {
+        let tree = init();
+        assert!(values(tree.pre_order()) == vec![1, 2, 4, 5, 3, 6]);
+        assert!(values(tree.in_order()) == vec![4, 2, 5, 1, 3, 6]);
+        assert!(values(tree.post_order()) == vec![4, 5, 2, 6, 3, 1]);
+        assert!(values(tree.level_order()) == vec![1, 2, 3, 4, 5, 6]);
+    }
+
+    #[test]
+    fn depth_and_path() {
+        let tree = init();
+        let five = tree.pre_order().find(|visit| *visit.value == 5).unwrap();
+        assert!(five.depth == 2);
+        assert!(five.path == vec![Direction::Left, Direction::Right]);
+    }
+
+    #[test]
+    fn subtree() {
+        let tree = init();
+        let two = tree.child(tree.root().unwrap(), Direction::Left).unwrap();
+        let visits: Vec<_> = tree.traverse_from(two, Order::InOrder).collect();
+        assert!(visits.iter().map(|visit| *visit.value).eq([4, 2, 5]));
+        assert!(visits[1].depth == 0 && visits[1].path.is_empty());
+    }
+
+    #[test]
+    fn empty() {
+        let tree: Tree<i32> = Tree::new();
+        assert!(tree.pre_order().next().is_none());
+    }
+
+    #[test]
+    fn visitor_early_exit() {
+        let tree = init();
+        let mut seen = vec![];
+        let found = tree.visit(Order::LevelOrder, |visit| {
+            seen.push(*visit.value);
+            match *visit.value > 2 {
+                true => ControlFlow::Break(visit.id),
+                false => ControlFlow::Continue(()),
+            }
+        });
+        assert!(seen == vec![1, 2, 3]);
+        assert!(found.map(|id| tree[id]) == Some(3));
+        assert!(tree.visit(Order::PreOrder, |_| ControlFlow::<()>::Continue(())).is_none());
+    }
+}
@@ -0,0 +1,333 @@
+//! The source map document format that the editor saves and
+//! the compiler reads. Older files are upgraded to the current
+//! format version when they are loaded.
+
+use std::{fs::{read_to_string, write}, path::Path};
+use anyhow::{Context, Result};
+use serde_json::{json, Value};
+use vfs::Vfs;
+use crate::{*, geometry::{Seg, TextureName}};
+
+/// The format version written by this version of the library.
+/// Bump this and add a step to `migrate` whenever the layout of
+/// `MapDocument` changes in a way that old files cannot be read.
+pub const FORMAT_VERSION: u32 = 1;
+
+/// The first version with a document around the geometry, which
+/// bare arrays of segs are upgraded to.
+const V1: u32 = 1;
+
+/// The smallest and largest grid sizes the editor allows.
+pub const MIN_GRID_SIZE: f32 = 1f32;
+pub const MAX_GRID_SIZE: f32 = 1024f32;
+
+/// The full source document for a map. Everything the editor
+/// knows about a map lives in here, not just the geometry.
+#[derive(Clone, Serialize, Deserialize, PartialEq, Debug)]
+pub struct MapDocument {
+    pub format_version: u32,
+    #[serde(default)]
+    pub metadata: MapMetadata,
+    #[serde(default)]
+    pub geometry: MapGeometry,
+    #[serde(default)]
+    pub things: Vec<Thing>,
+    #[serde(default)]
+    pub editor: EditorSettings,
+}
+
+/// Descriptive information about a map that does not affect
+/// its geometry.
+#[derive(Clone, Default, Serialize, Deserialize, PartialEq, Debug)]
+#[serde(default)]
+pub struct MapMetadata {
+    pub name: String,
+    pub author: String,
+    /// Name of the sky texture, if the map has one.
+    pub sky: Option<TextureName>,
+    /// Name of the music track, if the map has one.
+    pub music: Option<String>,
+}
+
+/// The walls and sectors that make up the map.
+#[derive(Clone, Default, Serialize, Deserialize, PartialEq, Debug)]
+#[serde(default)]
+pub struct MapGeometry {
+    pub segs: Vec<Seg>,
+    pub sectors: Vec<Sector>,
+}
+
+/// A sector is an area of the map with a single floor and
+/// ceiling height.
+#[derive(Clone, Serialize, Deserialize, PartialEq, Debug)]
+#[serde(default)]
+pub struct Sector {
+    pub floor_height: f32,
+    pub ceiling_height: f32,
+    pub floor_texture: TextureName,
+    pub ceiling_texture: TextureName,
+    pub light_level: u8,
+    /// Special behaviour number, 0 meaning none.
+    pub special: u32,
+}
+impl Default for Sector {
+    fn default() -> Self {
+        Sector {
+            floor_height: 0f32,
+            ceiling_height: 200f32,
+            floor_texture: TextureName::default(),
+            ceiling_texture: TextureName::default(),
+            light_level: 255,
+            special: 0,
+        }
+    }
+}
+
+/// A thing is anything placed in the map that is not geometry,
+/// such as the player start, enemies and pickups.
+#[derive(Clone, Copy, Default, Serialize, Deserialize, PartialEq, Debug)]
+#[serde(default)]
+pub struct Thing {
+    /// The type number of the thing, which decides what gets spawned.
+    pub kind: u32,
+    pub pos: Vec3,
+    pub angle_deg: f32,
+    pub flags: u32,
+}
+
+/// Settings that the editor stores alongside the map so that
+/// it reopens the way it was left.
+#[derive(Clone, Serialize, Deserialize, PartialEq, Debug)]
+#[serde(default)]
+pub struct EditorSettings {
+    /// Spacing of the grid in world units, a power of two.
+    pub grid_size: f32,
+    pub show_grid: bool,
+    pub snap_to_grid: bool,
+    /// Snap to the ends of existing lines.
+    pub snap_to_vertices: bool,
+    /// Snap to the middle of existing lines.
+    pub snap_to_midpoints: bool,
+}
+impl Default for EditorSettings {
+    fn default() -> Self {
+        EditorSettings {
+            grid_size: 32f32,
+            show_grid: true,
+            snap_to_grid: true,
+            snap_to_vertices: true,
+            snap_to_midpoints: false,
+        }
+    }
+}
+impl EditorSettings {
+    /// Round the grid size to the nearest power of two between
+    /// the smallest and largest sizes. A size that is not positive
+    /// goes back to the default.
+    pub fn clamp_grid_size(&mut self) {
+        self.grid_size = match self.grid_size {
+            size if size.is_nan() || size <= 0f32 => EditorSettings::default().grid_size,
+            size => 2f32.powf(size.log2().round()).clamp(MIN_GRID_SIZE, MAX_GRID_SIZE),
+        };
+    }
+}
+
+impl Default for MapDocument {
+    fn default() -> Self {
+        MapDocument {
+            format_version: FORMAT_VERSION,
+            metadata: MapMetadata::default(),
+            geometry: MapGeometry::default(),
+            things: vec![],
+            editor: EditorSettings::default(),
+        }
+    }
+}
+
+impl MapDocument {
+    /// Create an empty document at the current format version.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Create a document containing only the given segs.
+    pub fn from_segs(segs: Vec<Seg>) -> Self {
+        MapDocument {
+            geometry: MapGeometry { segs, ..Default::default() },
+            ..Default::default()
+        }
+    }
+
+    /// Parse a document from JSON, upgrading it from whichever
+    /// format version it was saved with. A grid size that the
+    /// editor could not have saved is clamped.
+    pub fn from_json(json: &str) -> Result<Self> {
+        let value: Value = serde_json::from_str(json)?;
+        let mut document: MapDocument = serde_json::from_value(migrate(value)?)?;
+        document.editor.clamp_grid_size();
+        Ok(document)
+    }
+
+    /// Serialise the document to pretty printed JSON.
+    pub fn to_json(&self) -> Result<String> {
+        Ok(serde_json::to_string_pretty(self)?)
+    }
+
+    /// Read and migrate a document from a file. Errors say which
+    /// file failed.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        let json = read_to_string(path).with_context(|| format!("Failed to read {}", path.display()))?;
+        Self::from_json(&json).with_context(|| format!("Failed to parse {}", path.display()))
+    }
+
+    /// The light level that a seg is drawn with, from the sector it
+    /// faces into. Segs without a sector are fully lit.
+    pub fn light_level(&self, seg: &Seg) -> u8 {
+        seg.sector()
+            .and_then(|sector| self.geometry.sectors.get(sector))
+            .map_or(255, |sector| sector.light_level)
+    }
+
+    /// Read and migrate a document through the virtual filesystem.
+    pub fn load_vfs(vfs: &Vfs, path: &str) -> Result<Self> {
+        Self::from_json(&vfs.read_to_string(path)?)
+    }
+
+    /// Write the document to a file.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let path = path.as_ref();
+        write(path, self.to_json()?).with_context(|| format!("Failed to write {}", path.display()))
+    }
+}
+
+/// Work out which format version a parsed document was saved
+/// with. Files from before the document format existed are a bare
+/// array of segs, which we call version 0.
+fn detect_version(value: &Value) -> Result<u32> {
+    match value {
+        Value::Array(_) => Ok(0),
+        Value::Object(map) => match map.get("format_version").and_then(Value::as_u64) {
+            Some(version) => u32::try_from(version)
+                .map_err(|_| anyhow!("Map document version {version} is out of range.")),
+            None => Err(anyhow!("Map document has no format version.")),
+        },
+        _ => Err(anyhow!("Map document is not an object or an array.")),
+    }
+}
+
+/// Upgrade a parsed document one version at a time until it
+/// reaches `FORMAT_VERSION`.
+fn migrate(mut value: Value) -> Result<Value> {
+    let mut version = detect_version(&value)?;
+    if version > FORMAT_VERSION {
+        return Err(anyhow!(
+            "Map document version {version} is newer than supported version {FORMAT_VERSION}."
+        ));
+    }
+
+    while version < FORMAT_VERSION {
+        value = match version {
+            0 => migrate_v0(value),
+            _ => unreachable!(),
+        };
+        version += 1;
+    }
+
+    Ok(value)
+}
+
+/// Version 0 is the bare array of segs, so it all goes into the
+/// geometry section.
+fn migrate_v0(segs: Value) -> Value {
+    json!({
+        "format_version": V1,
+        "geometry": { "segs": segs },
+    })
+}
+
+#[cfg(test)]
+pub mod tests {
+    use crate::document::*;
+    use crate::geometry::tests::init;
+
+    #[test]
+    fn round_trip() {
+        let (a, b, c, d) = init();
+        let mut document = MapDocument::from_segs(vec![a, b, c, d]);
+        document.metadata.name = "E1M1".to_owned();
+        document.things.push(Thing { kind: 1, ..Default::default() });
+
+        let json = document.to_json().unwrap();
+        assert!(MapDocument::from_json(&json).unwrap() == document)
+    }
+
+    #[test]
+    fn migrate_bare_segs() {
+        let (a, b, c, d) = init();
+        let segs = vec![a, b, c, d];
+        let json = serde_json::to_string_pretty(&segs).unwrap();
+
+        let document = MapDocument::from_json(&json).unwrap();
+        assert!(document.format_version == FORMAT_VERSION);
+        assert!(document == MapDocument::from_segs(segs))
+    }
+
+    #[test]
+    fn missing_sections_default() {
+        let document = MapDocument::from_json(r#"{ "format_version": 1 }"#).unwrap();
+        assert!(document == MapDocument::new())
+    }
+
+    #[test]
+    fn light_level() {
+        let (a, b, c, _) = init();
+        let mut document = MapDocument::from_segs(vec![a.with_sector(Some(0)), b.with_sector(Some(5)), c]);
+        document.geometry.sectors.push(Sector { light_level: 96, ..Default::default() });
+
+        let json = document.to_json().unwrap();
+        let document = MapDocument::from_json(&json).unwrap();
+        let segs = &document.geometry.segs;
+        assert!(document.light_level(&segs[0]) == 96);
+        assert!(document.light_level(&segs[1]) == 255);
+        assert!(document.light_level(&segs[2]) == 255);
+    }
+
+    #[test]
+    fn load_errors_name_the_file() {
+        let path = std::env::temp_dir().join("level_geometry_bad_map.json");
+        write(&path, "{ not json").unwrap();
+        let error = MapDocument::load(&path).unwrap_err();
+        assert!(error.to_string() == format!("Failed to parse {}", path.display()));
+
+        std::fs::remove_file(&path).unwrap();
+        let error = MapDocument::load(&path).unwrap_err();
+        assert!(error.to_string().starts_with("Failed to read"));
+    }
+
+    #[test]
+    fn grid_size_clamped() {
+        let grid_size = |size: &str| {
+            let json = format!(r#"{{"format_version": 1, "editor": {{"grid_size": {size}}}}}"#);
+            MapDocument::from_json(&json).unwrap().editor.grid_size
+        };
+        assert!(grid_size("64") == 64f32);
+        assert!(grid_size("0") == 32f32);
+        assert!(grid_size("-8") == 32f32);
+        assert!(grid_size("0.01") == MIN_GRID_SIZE);
+        assert!(grid_size("1e9") == MAX_GRID_SIZE);
+        assert!(grid_size("40") == 32f32);
+    }
+
+    #[test]
+    fn huge_version_fails() {
+        let json = format!(r#"{{ "format_version": {} }}"#, (1u64 << 32) + 1);
+        assert!(MapDocument::from_json(&json).is_err())
+    }
+
+    #[test]
+    fn newer_version_fails() {
+        let json = format!(r#"{{ "format_version": {} }}"#, FORMAT_VERSION + 1);
+        assert!(MapDocument::from_json(&json).is_err())
+    }
+}
@@ -1,6 +1,8 @@
 //! The structs and functions for describing level geometry
 
-use std::{f32::consts::PI, mem::swap};
+use std::{f32::consts::PI, fmt, mem::swap};
+use anyhow::Result;
+use glam::Vec2;
 use crate::*;
 
 /// Enum with types to represent the side
@@ -44,15 +46,130 @@ pub trait Line {
     fn determine_side<T: Line>(&self, other: T) -> Side;
 }
 
+/// The name of a texture, used for every texture a map or a texture
+/// manifest names. Names are at most eight bytes, like lump names
+/// in a WAD, so that segs can stay `Copy`.
+#[derive(Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct TextureName([u8; TextureName::MAX_LEN]);
+impl TextureName {
+    pub const MAX_LEN: usize = 8;
+
+    /// Fails if the name is too long or has a nul in it.
+    pub fn new(name: &str) -> Result<Self> {
+        if name.len() > Self::MAX_LEN || name.contains('\0') {
+            return Err(anyhow!("Texture name {name:?} is longer than {} bytes or has a nul.", Self::MAX_LEN));
+        }
+        let mut bytes = [0; Self::MAX_LEN];
+        bytes[..name.len()].copy_from_slice(name.as_bytes());
+        Ok(TextureName(bytes))
+    }
+
+    pub fn as_str(&self) -> &str {
+        let len = self.0.iter().position(|byte| *byte == 0).unwrap_or(Self::MAX_LEN);
+        std::str::from_utf8(&self.0[..len]).unwrap_or_default()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0[0] == 0
+    }
+}
+impl TryFrom<String> for TextureName {
+    type Error = anyhow::Error;
+
+    fn try_from(name: String) -> Result<Self> {
+        Self::new(&name)
+    }
+}
+impl From<TextureName> for String {
+    fn from(name: TextureName) -> Self {
+        name.as_str().to_owned()
+    }
+}
+impl fmt::Debug for TextureName {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?}", self.as_str())
+    }
+}
+impl fmt::Display for TextureName {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
 /// A seg is a portion of a linedef
 #[derive(Clone, Copy, Serialize, Deserialize, PartialEq, Debug)]
 pub struct Seg {
     points: (Vec3, Vec3),
     height: f32,
+    /// Index of the sector the seg faces into, if it has one.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    sector: Option<usize>,
+    /// The texture on the wall, empty for none.
+    #[serde(default, skip_serializing_if = "TextureName::is_empty")]
+    texture: TextureName,
+    /// How far the texture is shifted along and up the wall.
+    #[serde(default)]
+    texture_offset: Vec2,
+    /// Behaviour flags, 0 meaning none.
+    #[serde(default)]
+    flags: u32,
+}
+impl Seg {
+    /// The same seg, facing into a certain sector.
+    pub fn with_sector(self, sector: Option<usize>) -> Self {
+        Seg { sector, ..self }
+    }
+
+    /// The same seg, moved to new points.
+    pub fn with_points(self, points: (Vec3, Vec3)) -> Self {
+        Seg { points, ..self }
+    }
+
+    /// Index of the sector the seg faces into, if it has one.
+    pub fn sector(&self) -> Option<usize> {
+        self.sector
+    }
+
+    /// The same seg, with a different height above its points.
+    pub fn with_height(self, height: f32) -> Self {
+        Seg { height, ..self }
+    }
+
+    pub fn with_texture(self, texture: TextureName) -> Self {
+        Seg { texture, ..self }
+    }
+
+    pub fn texture(&self) -> TextureName {
+        self.texture
+    }
+
+    pub fn with_texture_offset(self, texture_offset: Vec2) -> Self {
+        Seg { texture_offset, ..self }
+    }
+
+    pub fn texture_offset(&self) -> Vec2 {
+        self.texture_offset
+    }
+
+    pub fn with_flags(self, flags: u32) -> Self {
+        Seg { flags, ..self }
+    }
+
+    pub fn flags(&self) -> u32 {
+        self.flags
+    }
 }
 impl Line for Seg {
     fn new(points: (Vec3, Vec3), height: f32) -> Self {
-        Seg { points, height }
+        Seg {
+            points,
+            height,
+            sector: None,
+            texture: TextureName::default(),
+            texture_offset: Vec2::ZERO,
+            flags: 0,
+        }
     }
 
     fn height(&self) -> f32 {
@@ -103,8 +220,8 @@ impl Line for Seg {
                     {
                         false => None,
                         true => Some((
-                            Seg::new((self.points().0, intersection_point), self.height),
-                            Seg::new((intersection_point, self.points().1), self.height),
+                            self.with_points((self.points().0, intersection_point)),
+                            self.with_points((intersection_point, self.points().1)),
                         )),
                     },
                 }
@@ -211,19 +328,19 @@ pub mod tests {
     #[test]
     fn parallel() {
         let (seg0, seg1, _, _) = init();
-        assert!(seg0.intersects(seg1) == false)
+        assert!(!seg0.intersects(seg1))
     }
 
     #[test]
     fn not_parallel() {
         let (seg0, _, seg2, _) = init();
-        assert!(seg0.intersects(seg2) == true)
+        assert!(seg0.intersects(seg2))
     }
 
     #[test]
     fn split_parallel() {
         let (seg0, seg1, _, _) = init();
-        assert!(seg0.split_by(seg1).is_none() == true)
+        assert!(seg0.split_by(seg1).is_none())
     }
 
     #[test]
@@ -250,7 +367,7 @@ pub mod tests {
         let (seg0, _, _, seg3) = init();
         let split = seg0.split_by(seg3);
         dbg!(&split);
-        assert!(split.is_none() == true)
+        assert!(split.is_none())
     }
 
     #[test]
@@ -272,7 +389,7 @@ pub mod tests {
     fn normal_swap() {
         let (seg0, _, _, _) = init();
 
-        let mut flip_seg0 = seg0.clone();
+        let mut flip_seg0 = seg0;
         flip_seg0.flip();
 
         assert!(seg0.normal() != flip_seg0.normal())
@@ -296,4 +413,29 @@ pub mod tests {
         let (seg0, _, seg2, _) = init();
         assert!(seg0.determine_side(seg2) == Side::Neither)
     }
+
+    #[test]
+    fn split_keeps_properties() {
+        let (seg0, _, seg2, _) = init();
+        let seg0 = seg0
+            .with_sector(Some(2))
+            .with_texture(TextureName::new("STARTAN3").unwrap())
+            .with_flags(1);
+        let (newseg0, newseg1) = seg0.split_by(seg2).unwrap();
+        assert!(newseg0.texture() == seg0.texture() && newseg1.texture() == seg0.texture());
+        assert!(newseg0.sector() == Some(2) && newseg1.flags() == 1)
+    }
+
+    #[test]
+    fn texture_names() {
+        let name = TextureName::new("BROWN1").unwrap();
+        assert!(name.as_str() == "BROWN1" && !name.is_empty());
+        assert!(TextureName::default().is_empty());
+        assert!(TextureName::new("TOOLONGNAME").is_err());
+
+        let json = serde_json::to_string(&name).unwrap();
+        assert!(json == r#""BROWN1""#);
+        assert!(serde_json::from_str::<TextureName>(&json).unwrap() == name);
+        assert!(serde_json::from_str::<TextureName>(r#""TOOLONGNAME""#).is_err())
+    }
 }
@@ -8,5 +8,6 @@ use anyhow::anyhow;
 use glam::Vec3;
 use serde::{Deserialize, Serialize};
 
+pub mod document;
 pub mod geometry;
 pub mod partitioning;
\ No newline at end of file
@@ -1,57 +1,29 @@
 //! The file for functions that partition the level geometry
 
-#[allow(unused_imports)]
-
-use crate::{*, geometry::*};
+use crate::{document::MapDocument, geometry::*};
 use binary_tree::*;
 
-/// Split a vector of segs into a node with itself as root
+/// Split a vector of segs into a tree with itself as root
 /// and the child nodes containing on the left, nodes behind,
 /// and on the right, nodes in front.
-// unholy code duplication, forgive me o great graydon hoare
-fn front_back(segs: Vec<Seg>) -> NodeRef<Vec<Seg>> {
-    // Give it children. We can unwrap here as we know that we 
-    // will not get a panic because we are not trying to 
-    // overwrite a preexisting child.
-    let mut segs_node = Node::new(segs);
-
-    segs_node.create_child(vec![], Direction::Left).unwrap();
-    segs_node.create_child(vec![], Direction::Right).unwrap();
-
-    if segs_node.value.len() == 2 {
-        let root = segs_node.value[0];
-        let other = segs_node.value[1];
-        match root.determine_side(other.clone()) {
+fn front_back(segs: Vec<Seg>) -> Result<Tree<Vec<Seg>>, TreeError> {
+    // Give it children.
+    let mut tree = Tree::with_root(segs);
+    let root_id = tree.root().ok_or(TreeError::EmptyTree)?;
+    let back = tree.create_child(root_id, vec![], Direction::Left)?;
+    let front = tree.create_child(root_id, vec![], Direction::Right)?;
+
+    if tree[root_id].len() == 2 {
+        let root = tree[root_id][0];
+        let other = tree[root_id][1];
+        match root.determine_side(other) {
             Side::Front => {
-                segs_node
-                    .right.as_mut()
-                    // This should never happen
-                    .ok_or_else(|| panic!())
-                    .unwrap()
-                    .borrow_mut()
-                    .value.push(other);
-                segs_node
-                    .left.as_mut()
-                    // This should never happen
-                    .ok_or_else(|| panic!())
-                    .unwrap()
-                    .borrow_mut()
-                    .value.push(other)
+                tree[front].push(other);
+                tree[back].push(other);
             },
             Side::Back => {
-                segs_node
-                    .left.as_mut()
-                    .ok_or_else(|| panic!())
-                    .unwrap()
-                    .borrow_mut()
-                    .value.push(other);
-                segs_node
-                    .right.as_mut()
-                    // This should never happen
-                    .ok_or_else(|| panic!())
-                    .unwrap()
-                    .borrow_mut()
-                    .value.push(other)
+                tree[back].push(other);
+                tree[front].push(other);
             },
             Side::Neither => {
                 // Split the seg
@@ -59,46 +31,18 @@ fn front_back(segs: Vec<Seg>) -> NodeRef<Vec<Seg>> {
                 // Make sure it split
                 match split {
                     // If it didn't, just send it to the back
-                    None =>  segs_node
-                        .left.as_mut()
-                        .ok_or_else(|| panic!())
-                        .unwrap()
-                        .borrow_mut()
-                        .value.push(other),
+                    None => tree[back].push(other),
                     Some(segs) => {
                         let segvec = vec![segs.0, segs.1];
                         for seg in segvec {
-                            match root.determine_side(seg.clone()) {
+                            match root.determine_side(seg) {
                                 Side::Front => {
-                                    segs_node
-                                        .right.as_mut()
-                                        // This should never happen
-                                        .ok_or_else(|| panic!())
-                                        .unwrap()
-                                        .borrow_mut()
-                                        .value.push(seg);
-                                    segs_node
-                                        .left.as_mut()
-                                        // This should never happen
-                                        .ok_or_else(|| panic!())
-                                        .unwrap()
-                                        .borrow_mut()
-                                        .value.push(other);
+                                    tree[front].push(seg);
+                                    tree[back].push(other);
                                 },
                                 Side::Back => {
-                                    segs_node
-                                        .left.as_mut()
-                                        .ok_or_else(|| panic!())
-                                        .unwrap()
-                                        .borrow_mut()
-                                        .value.push(seg);
-                                    segs_node
-                                        .right.as_mut()
-                                        // This should never happen
-                                        .ok_or_else(|| panic!())
-                                        .unwrap()
-                                        .borrow_mut()
-                                        .value.push(other);
+                                    tree[back].push(seg);
+                                    tree[front].push(other);
                                 },
                                 // It should never be neither again, so we panic.
                                 Side::Neither => panic!(),
@@ -108,56 +52,30 @@ fn front_back(segs: Vec<Seg>) -> NodeRef<Vec<Seg>> {
                 }
             },
         }
-        return segs_node.noderef()
+        return Ok(tree)
     }
 
     // Iterate over the root node's item, adding to each child
     // for front and back.
-    let root: Seg = segs_node.value[0];
-    segs_node.value.iter_mut().for_each(|seg| {
-        match root.determine_side(seg.clone()) {
-            Side::Front => segs_node
-                .right.as_mut()
-                // This should never happen
-                .ok_or_else(|| panic!())
-                .unwrap()
-                .borrow_mut()
-                .value.push(*seg),
-            Side::Back => segs_node
-                .left.as_mut()
-                .ok_or_else(|| panic!())
-                .unwrap()
-                .borrow_mut()
-                .value.push(*seg),
+    let segs = tree[root_id].clone();
+    let root: Seg = segs[0];
+    for seg in segs {
+        match root.determine_side(seg) {
+            Side::Front => tree[front].push(seg),
+            Side::Back => tree[back].push(seg),
             Side::Neither => {
                 // Split the seg
                 let split = seg.split_by(root);
                 // Make sure it split
                 match split {
                     // If it didn't, just send it to the back
-                    None =>  segs_node
-                        .left.as_mut()
-                        .ok_or_else(|| panic!())
-                        .unwrap()
-                        .borrow_mut()
-                        .value.push(*seg),
+                    None => tree[back].push(seg),
                     Some(segs) => {
                         let segvec = vec![segs.0, segs.1];
                         for seg in segvec {
-                            match root.determine_side(seg.clone()) {
-                                Side::Front => segs_node
-                                    .right.as_mut()
-                                    // This should never happen
-                                    .ok_or_else(|| panic!())
-                                    .unwrap()
-                                    .borrow_mut()
-                                    .value.push(seg),
-                                Side::Back => segs_node
-                                    .left.as_mut()
-                                    .ok_or_else(|| panic!())
-                                    .unwrap()
-                                    .borrow_mut()
-                                    .value.push(seg),
+                            match root.determine_side(seg) {
+                                Side::Front => tree[front].push(seg),
+                                Side::Back => tree[back].push(seg),
                                 // It should never be neither again, so we panic.
                                 Side::Neither => panic!(),
                             }
@@ -166,58 +84,58 @@ fn front_back(segs: Vec<Seg>) -> NodeRef<Vec<Seg>> {
                 }
             },
         }
-    });
+    }
 
-    segs_node.noderef()
+    Ok(tree)
 }
 
 // this code sucks
-//// pub fn recursive_partition(seglists: Vec<Vec<Seg>>) -> Vec<Seg> {
-////     let mut new_seglists: Vec<Vec<Seg>> = Vec::new();
-////     // iterate over the segs
-////     for segs in seglists {
-////         // if the length is 1 do not touch it
-////         match segs.len()  {
-////             0usize | 1usize => new_seglists.push(segs.clone()),
-////             _ => {
-////             // If length is not 1, we must partition, get the front and back then recurse
-////                 let partitioned = front_back(segs.to_owned()); 
-////                 let front_segs = partitioned
-////                     .borrow()
-////                     .get_child_reference(Direction::Left)
-////                     .unwrap()
-////                     .borrow()
-////                     .value.clone();
-////                 let back_segs = partitioned
-////                     .borrow()
-////                    .get_child_reference(Direction::Right)
-////                     .unwrap()
-////                     .borrow()
-////                     .value.clone();
-////                
-////                 // Put the two halves into one thing to recurse with
-////                 new_seglists.push(recursive_partition(vec![front_segs, back_segs]));
-////             }
-////         }
-////     }     
-////
-////     new_seglists.iter().fold(vec![], |acc: Vec<Seg>, segs| {
-////         let mut new_segs = acc.clone();
-////         new_segs.push(segs[0]);
-////         new_segs
-////     })
-//// }
+// pub fn recursive_partition(seglists: Vec<Vec<Seg>>) -> Vec<Seg> {
+//     let mut new_seglists: Vec<Vec<Seg>> = Vec::new();
+//     // iterate over the segs
+//     for segs in seglists {
+//         // if the length is 1 do not touch it
+//         match segs.len()  {
+//             0usize | 1usize => new_seglists.push(segs.clone()),
+//             _ => {
+//             // If length is not 1, we must partition, get the front and back then recurse
+//                 let partitioned = front_back(segs.to_owned()); 
+//                 let front_segs = partitioned
+//                     .borrow()
+//                     .get_child_reference(Direction::Left)
+//                     .unwrap()
+//                     .borrow()
+//                     .value.clone();
+//                 let back_segs = partitioned
+//                     .borrow()
+//                    .get_child_reference(Direction::Right)
+//                     .unwrap()
+//                     .borrow()
+//                     .value.clone();
+//                
+//                 // Put the two halves into one thing to recurse with
+//                 new_seglists.push(recursive_partition(vec![front_segs, back_segs]));
+//             }
+//         }
+//     }     
+//
+//     new_seglists.iter().fold(vec![], |acc: Vec<Seg>, segs| {
+//         let mut new_segs = acc.clone();
+//         new_segs.push(segs[0]);
+//         new_segs
+//     })
+// }
 
 /// This is a non-recursive version of the algorithm to repeatedly partition
 /// the map segs until no partitions occur. 
-pub fn non_recursive_partition(initial_segs: Vec<Seg>) -> Vec<Seg> {
+pub fn non_recursive_partition(initial_segs: Vec<Seg>) -> Result<Vec<Seg>, TreeError> {
     let mut segsvec = vec![initial_segs];
     let mut flag = true;
     let mut iterations = 0;
     
     // Flag while loop
     while flag && iterations <= 100 {
-        iterations = iterations + 1;
+        iterations += 1;
         let mut newsegsvec: Vec<Vec<Seg>> = vec![];
         flag = false;
 
@@ -229,20 +147,16 @@ pub fn non_recursive_partition(initial_segs: Vec<Seg>) -> Vec<Seg> {
             flag = true;
 
             // Partition then get front and back segs
-            let partitioned = front_back(segs);
-            let front = partitioned
-                .borrow()
-                .get_child_reference(Direction::Left)
-                .unwrap()
-                .borrow()
-                .value.clone();
-
-            let back = partitioned
-                .borrow()
-                .get_child_reference(Direction::Right)
-                .unwrap()
-                .borrow()
-                .value.clone();
+            let mut partitioned = front_back(segs)?;
+            let root = partitioned.root().ok_or(TreeError::EmptyTree)?;
+            let mut take = |direction| {
+                partitioned
+                    .child(root, direction)
+                    .and_then(|id| partitioned.remove(id))
+                    .ok_or(TreeError::NoSuchChild { direction })
+            };
+            let front = take(Direction::Left)?;
+            let back = take(Direction::Right)?;
 
             // push the new front and back segs
             newsegsvec.push(front);
@@ -253,7 +167,7 @@ pub fn non_recursive_partition(initial_segs: Vec<Seg>) -> Vec<Seg> {
     }
 
     // Fold the values back into one vec and return
-    segsvec.iter().fold(vec![], |acc: Vec<Seg>, segs| {
+    Ok(segsvec.iter().fold(vec![], |acc: Vec<Seg>, segs| {
         match segs.len() {
             0usize => acc,
             _ => { 
@@ -262,7 +176,13 @@ pub fn non_recursive_partition(initial_segs: Vec<Seg>) -> Vec<Seg> {
                 new 
             }
         }
-    })
+    }))
+}
+
+/// Compile the geometry of a source map document into the
+/// partitioned segs that the engine loads.
+pub fn compile_document(document: &MapDocument) -> Result<Vec<Seg>, TreeError> {
+    non_recursive_partition(document.geometry.segs.clone())
 }
 
 #[allow(unused_imports)]
@@ -274,20 +194,22 @@ pub mod tests {
     fn no_panic_sanity_check() {
         let (seg0, seg1, seg2, seg3) = init();
         let segvec = vec![seg0, seg1, seg2, seg3];
-        dbg!(front_back(segvec));
+        let tree = front_back(segvec).unwrap();
+        let ascii = tree.to_ascii(|segs| format!("{} segs", segs.len()));
+        assert!(ascii == "4 segs\n├── L: 1 segs\n└── R: 3 segs\n");
     }
 
-    //// #[test]
-    //// fn recursive_panic_test() {
-    ////     let (a, b, c, d) = init();
-    ////     let segvecs = vec![vec![a, b, c, d]];
-    ////     dbg!(recursive_partition(segvecs));
-    //// }
+    // #[test]
+    // fn recursive_panic_test() {
+    //     let (a, b, c, d) = init();
+    //     let segvecs = vec![vec![a, b, c, d]];
+    //     dbg!(recursive_partition(segvecs));
+    // }
     
     #[test]
     fn non_recursive_panic_test() {
         let (a, b, c, d) = init();
         let segs = vec![a, b, c, d];
-        non_recursive_partition(segs);
+        non_recursive_partition(segs).unwrap();
     }
 }
\ No newline at end of file
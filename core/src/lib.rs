@@ -1,5 +1,6 @@
-///! Core engine libraries
+//! Core engine libraries
 
 pub use level_geometry;
 pub use binary_tree;
-pub use textures;
\ No newline at end of file
+pub use textures;
+pub use vfs;
\ No newline at end of file
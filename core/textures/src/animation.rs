@@ -0,0 +1,174 @@
+//! Texture animation, both flipping between frames like
+//! Doom's NUKAGE1..3 and scrolling a texture's UVs. Animations
+//! are defined in the manifest and only depend on the game
+//! time, so every surface using a texture stays in step.
+
+use level_geometry::geometry::TextureName;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use crate::manifest::TextureManifest;
+
+/// One frame of a frame sequence.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct AnimationFrame {
+    /// Name of the texture to draw for this frame.
+    pub texture: TextureName,
+    /// How long the frame is shown for, in seconds.
+    pub duration: f32,
+}
+
+/// How a texture changes over time. A texture can have frames,
+/// scrolling, or both.
+#[derive(Clone, Default, Debug, PartialEq, Serialize, Deserialize)]
+pub struct AnimatedTexture {
+    /// Frames that are looped through in order.
+    #[serde(default)]
+    pub frames: Vec<AnimationFrame>,
+    /// UV offset added every second.
+    #[serde(default)]
+    pub scroll: [f32; 2],
+}
+impl AnimatedTexture {
+    /// The length of one loop of the frames in seconds.
+    pub fn duration(&self) -> f32 {
+        self.frames.iter().map(|frame| frame.duration.max(0.0)).sum()
+    }
+
+    /// The texture to draw at a game time, or `None` if there are
+    /// no frames and the texture itself should be drawn.
+    pub fn frame_at(&self, time: f32) -> Option<&str> {
+        let duration = self.duration();
+        if duration <= 0.0 {
+            return self.frames.first().map(|frame| frame.texture.as_str());
+        }
+        let mut time = time.rem_euclid(duration);
+        for frame in &self.frames {
+            let length = frame.duration.max(0.0);
+            if time < length {
+                return Some(frame.texture.as_str());
+            }
+            time -= length;
+        }
+        // Rounding can leave the time just past the last frame
+        self.frames.last().map(|frame| frame.texture.as_str())
+    }
+
+    /// The UV offset at a game time, wrapped into 0 to 1.
+    pub fn offset_at(&self, time: f32) -> [f32; 2] {
+        [
+            (self.scroll[0] * time).rem_euclid(1.0),
+            (self.scroll[1] * time).rem_euclid(1.0),
+        ]
+    }
+}
+
+/// A texture as it should be drawn right now.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ResolvedTexture<'a> {
+    pub texture: &'a str,
+    pub offset: [f32; 2],
+}
+
+/// Keeps the game time and the animations from a manifest, so
+/// that the engine can advance it every tick and look up what
+/// to draw for each texture name.
+#[derive(Clone, Default, Debug, PartialEq)]
+pub struct TextureAnimator {
+    time: f32,
+    animations: BTreeMap<String, AnimatedTexture>,
+}
+impl TextureAnimator {
+    /// Collect the animation of every animated texture in a manifest.
+    pub fn from_manifest(manifest: &TextureManifest) -> Self {
+        let animations = manifest
+            .textures
+            .iter()
+            .filter_map(|entry| Some((entry.name.to_string(), entry.animation.clone()?)))
+            .collect();
+        TextureAnimator { time: 0.0, animations }
+    }
+
+    /// Add or replace the animation of a texture.
+    pub fn insert(&mut self, name: &str, animation: AnimatedTexture) {
+        self.animations.insert(name.to_owned(), animation);
+    }
+
+    /// Returns whether no texture is animated.
+    pub fn is_empty(&self) -> bool {
+        self.animations.is_empty()
+    }
+
+    /// Advance the game time by `delta` seconds.
+    pub fn tick(&mut self, delta: f32) {
+        self.time += delta;
+    }
+
+    /// The current game time in seconds.
+    pub fn time(&self) -> f32 {
+        self.time
+    }
+
+    /// What to draw for a texture name at the current time.
+    /// Textures without an animation resolve to themselves.
+    pub fn resolve<'a>(&'a self, name: &'a str) -> ResolvedTexture<'a> {
+        match self.animations.get(name) {
+            Some(animation) => ResolvedTexture {
+                texture: animation.frame_at(self.time).unwrap_or(name),
+                offset: animation.offset_at(self.time),
+            },
+            None => ResolvedTexture {
+                texture: name,
+                offset: [0.0; 2],
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use crate::animation::*;
+
+    fn nukage() -> AnimatedTexture {
+        AnimatedTexture {
+            frames: vec![
+                AnimationFrame { texture: TextureName::new("NUKAGE1").unwrap(), duration: 0.25 },
+                AnimationFrame { texture: TextureName::new("NUKAGE2").unwrap(), duration: 0.25 },
+                AnimationFrame { texture: TextureName::new("NUKAGE3").unwrap(), duration: 0.5 },
+            ],
+            scroll: [0.0; 2],
+        }
+    }
+
+    #[test]
+    fn frames() {
+        let animation = nukage();
+        assert!(animation.duration() == 1.0);
+        assert!(animation.frame_at(0.0) == Some("NUKAGE1"));
+        assert!(animation.frame_at(0.3) == Some("NUKAGE2"));
+        assert!(animation.frame_at(0.9) == Some("NUKAGE3"));
+        // Loops around
+        assert!(animation.frame_at(1.1) == Some("NUKAGE1"));
+        assert!(AnimatedTexture::default().frame_at(1.0).is_none());
+    }
+
+    #[test]
+    fn scrolling() {
+        let animation = AnimatedTexture {
+            frames: vec![],
+            scroll: [0.5, -0.25],
+        };
+        assert!(animation.offset_at(1.0) == [0.5, 0.75]);
+        assert!(animation.offset_at(3.0) == [0.5, 0.25]);
+    }
+
+    #[test]
+    fn animator() {
+        let mut animator = TextureAnimator::default();
+        assert!(animator.is_empty());
+        animator.insert("NUKAGE1", nukage());
+        animator.tick(0.5);
+        animator.tick(0.1);
+        assert!(animator.resolve("NUKAGE1").texture == "NUKAGE3");
+        assert!(animator.resolve("STARTAN3") == ResolvedTexture { texture: "STARTAN3", offset: [0.0; 2] });
+    }
+}
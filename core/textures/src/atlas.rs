@@ -0,0 +1,263 @@
+//! Packing of many images into a few large atlas pages, so
+//! that walls can share a texture binding. Packing only
+//! depends on the images and options given, so the same
+//! inputs always give the same atlas.
+
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use crate::image_data::ImageData;
+
+/// Settings for packing an atlas.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct AtlasOptions {
+    /// Width and height of each atlas page in pixels.
+    pub page_size: u32,
+    /// Transparent gap left between neighbouring images.
+    pub padding: u32,
+    /// Number of pixels that each image's edge is copied outwards,
+    /// so filtering at the edge of an image does not pick up its
+    /// neighbours.
+    pub bleed: u32,
+}
+impl Default for AtlasOptions {
+    fn default() -> Self {
+        AtlasOptions {
+            page_size: 1024,
+            padding: 2,
+            bleed: 1,
+        }
+    }
+}
+
+/// Where an image ended up in the atlas.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct AtlasRegion {
+    /// Index of the page the image is on.
+    pub page: usize,
+    /// Pixel position of the image's top left corner on the page,
+    /// not counting bleed.
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+    /// UV of the image's top left corner on the page.
+    pub uv_min: (f32, f32),
+    /// UV of the image's bottom right corner on the page.
+    pub uv_max: (f32, f32),
+}
+impl AtlasRegion {
+    /// Turn a UV in the space of the original image into a UV on
+    /// the atlas page. UVs outside of 0 to 1 are not wrapped, so
+    /// tiling has to be done by splitting the geometry.
+    pub fn remap_uv(&self, uv: (f32, f32)) -> (f32, f32) {
+        (
+            self.uv_min.0 + uv.0 * (self.uv_max.0 - self.uv_min.0),
+            self.uv_min.1 + uv.1 * (self.uv_max.1 - self.uv_min.1),
+        )
+    }
+}
+
+/// A packed atlas, with the page images and a table of where
+/// every texture went, keyed by texture name.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Atlas {
+    pub pages: Vec<ImageData>,
+    pub regions: BTreeMap<String, AtlasRegion>,
+}
+impl Atlas {
+    /// Get the region of a texture by name.
+    pub fn region(&self, name: &str) -> Option<&AtlasRegion> {
+        self.regions.get(name)
+    }
+
+    /// Remap a UV of a named texture onto its atlas page.
+    pub fn remap_uv(&self, name: &str, uv: (f32, f32)) -> Option<(f32, f32)> {
+        self.region(name).map(|region| region.remap_uv(uv))
+    }
+}
+
+/// Pack images into as many atlas pages as are needed, using
+/// shelves filled from the top left. Images are placed tallest
+/// first, with ties broken by width and then name, so the input
+/// order does not matter.
+pub fn pack(images: &[ImageData], options: &AtlasOptions) -> Result<Atlas> {
+    let mut order: Vec<&ImageData> = images.iter().collect();
+    order.sort_by(|a, b| {
+        b.height
+            .cmp(&a.height)
+            .then(b.width.cmp(&a.width))
+            .then(a.name.cmp(&b.name))
+    });
+
+    let border = options.bleed * 2 + options.padding;
+    let mut pages: Vec<ImageData> = vec![];
+    let mut regions = BTreeMap::new();
+    // Top left of the next cell, and the height of the current shelf
+    let (mut x, mut y, mut shelf) = (0, 0, 0);
+
+    for image in order {
+        if regions.contains_key(&image.name) {
+            return Err(anyhow!("Texture {} was given to the atlas twice.", image.name));
+        }
+        if image.width == 0 || image.height == 0 {
+            return Err(anyhow!("Texture {} has no pixels to put in the atlas.", image.name));
+        }
+        let (cell_width, cell_height) = (image.width + border, image.height + border);
+        if cell_width > options.page_size || cell_height > options.page_size {
+            return Err(anyhow!(
+                "Texture {} is too big for an atlas page of {}.",
+                image.name,
+                options.page_size
+            ));
+        }
+
+        // Move down to a new shelf, or on to a new page
+        if x + cell_width > options.page_size {
+            (x, y, shelf) = (0, y + shelf, 0);
+        }
+        if pages.is_empty() || y + cell_height > options.page_size {
+            let name = format!("atlas{}", pages.len());
+            pages.push(ImageData::filled(&name, options.page_size, options.page_size, [0; 4])?);
+            (x, y, shelf) = (0, 0, 0);
+        }
+
+        let page = pages.len() - 1;
+        let (inner_x, inner_y) = (x + options.bleed, y + options.bleed);
+        blit(&mut pages[page], image, inner_x, inner_y, options.bleed);
+
+        let size = options.page_size as f32;
+        regions.insert(
+            image.name.clone(),
+            AtlasRegion {
+                page,
+                x: inner_x,
+                y: inner_y,
+                width: image.width,
+                height: image.height,
+                uv_min: (inner_x as f32 / size, inner_y as f32 / size),
+                uv_max: (
+                    (inner_x + image.width) as f32 / size,
+                    (inner_y + image.height) as f32 / size,
+                ),
+            },
+        );
+
+        x += cell_width;
+        shelf = shelf.max(cell_height);
+    }
+
+    Ok(Atlas { pages, regions })
+}
+
+/// Copy `image` onto `page` at `x`, `y`, then extend its edge
+/// pixels outwards by `bleed` pixels on every side.
+fn blit(page: &mut ImageData, image: &ImageData, x: u32, y: u32, bleed: u32) {
+    let bleed = bleed as i64;
+    for dy in -bleed..image.height as i64 + bleed {
+        for dx in -bleed..image.width as i64 + bleed {
+            let source_x = dx.clamp(0, image.width as i64 - 1) as u32;
+            let source_y = dy.clamp(0, image.height as i64 - 1) as u32;
+            let colour = image.pixel(source_x, source_y);
+            page.set_pixel((x as i64 + dx) as u32, (y as i64 + dy) as u32, colour);
+        }
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use crate::atlas::*;
+
+    fn images() -> Vec<ImageData> {
+        vec![
+            ImageData::filled("wide", 8, 2, [255, 0, 0, 255]).unwrap(),
+            ImageData::filled("tall", 2, 8, [0, 255, 0, 255]).unwrap(),
+            ImageData::filled("square", 4, 4, [0, 0, 255, 255]).unwrap(),
+        ]
+    }
+
+    fn options() -> AtlasOptions {
+        AtlasOptions {
+            page_size: 16,
+            padding: 1,
+            bleed: 1,
+        }
+    }
+
+    fn overlaps(a: &AtlasRegion, b: &AtlasRegion) -> bool {
+        a.page == b.page
+            && a.x < b.x + b.width
+            && b.x < a.x + a.width
+            && a.y < b.y + b.height
+            && b.y < a.y + a.height
+    }
+
+    #[test]
+    fn packs_without_overlap() {
+        let atlas = pack(&images(), &options()).unwrap();
+        let regions: Vec<&AtlasRegion> = atlas.regions.values().collect();
+        for (i, a) in regions.iter().enumerate() {
+            assert!(a.x + a.width <= 16 && a.y + a.height <= 16);
+            for b in &regions[i + 1..] {
+                assert!(!overlaps(a, b));
+            }
+        }
+    }
+
+    #[test]
+    fn copies_pixels_and_bleed() {
+        let atlas = pack(&images(), &options()).unwrap();
+        let tall = atlas.region("tall").unwrap();
+        let page = &atlas.pages[tall.page];
+        assert!(page.pixel(tall.x, tall.y) == [0, 255, 0, 255]);
+        // The bleed copies the edge outwards
+        assert!(page.pixel(tall.x - 1, tall.y - 1) == [0, 255, 0, 255]);
+        assert!(page.pixel(tall.x + tall.width, tall.y) == [0, 255, 0, 255]);
+        // The padding beyond the bleed stays empty
+        assert!(page.pixel(tall.x + tall.width + 1, tall.y) == [0; 4]);
+    }
+
+    #[test]
+    fn deterministic() {
+        let mut reversed = images();
+        reversed.reverse();
+        assert!(pack(&images(), &options()).unwrap() == pack(&reversed, &options()).unwrap());
+    }
+
+    #[test]
+    fn remap_uv() {
+        let atlas = pack(&images(), &options()).unwrap();
+        let square = atlas.region("square").unwrap();
+        assert!(atlas.remap_uv("square", (0.0, 0.0)) == Some(square.uv_min));
+        assert!(atlas.remap_uv("square", (1.0, 1.0)) == Some(square.uv_max));
+        assert!(atlas.remap_uv("nothing", (0.5, 0.5)).is_none());
+    }
+
+    #[test]
+    fn overflows_onto_new_page() {
+        let images: Vec<ImageData> = (0..5)
+            .map(|i| ImageData::filled(&format!("block{i}"), 4, 4, [i; 4]).unwrap())
+            .collect();
+        let atlas = pack(&images, &options()).unwrap();
+        assert!(atlas.pages.len() == 2);
+        assert!(atlas.regions.values().filter(|region| region.page == 1).count() == 1);
+    }
+
+    #[test]
+    fn errors() {
+        let too_big = vec![ImageData::filled("big", 16, 16, [0; 4]).unwrap()];
+        assert!(pack(&too_big, &options()).is_err());
+        let twice = vec![
+            ImageData::filled("a", 1, 1, [0; 4]).unwrap(),
+            ImageData::filled("a", 2, 2, [0; 4]).unwrap(),
+        ];
+        assert!(pack(&twice, &options()).is_err());
+        let empty = vec![ImageData {
+            name: "empty".to_owned(),
+            width: 0,
+            height: 2,
+            pixels: vec![],
+        }];
+        assert!(pack(&empty, &options()).is_err());
+    }
+}
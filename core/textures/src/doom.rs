@@ -0,0 +1,496 @@
+//! Decoding of the graphics lumps used by Doom WAD files:
+//! the PLAYPAL palettes, the COLORMAP light tables, pictures
+//! in the patch format, raw flats, and the wall textures that
+//! TEXTURE1/TEXTURE2 build out of PNAMES patches. Everything
+//! works on the bytes of lumps, so finding the lumps in a WAD
+//! is left to the caller.
+
+use anyhow::{anyhow, Result};
+use std::collections::HashMap;
+use crate::image_data::ImageData;
+
+/// The number of colours in a palette.
+pub const PALETTE_SIZE: usize = 256;
+
+/// The width and height of a flat.
+pub const FLAT_SIZE: u32 = 64;
+
+/// The most pixels a patch lump can describe per byte. A post takes
+/// at least four bytes and reaches at most 509 rows further down,
+/// so anything claiming more than this is not a real patch.
+const MAX_PATCH_PIXELS_PER_BYTE: usize = 128;
+
+/// The widest and tallest a composite wall texture can be. Sizes in
+/// a TEXTURE lump go up to 65535, far past any real texture.
+pub const MAX_TEXTURE_SIZE: u32 = 4096;
+
+/// A palette of 256 RGB colours that pixel indices refer to.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Palette {
+    pub colours: Vec<[u8; 3]>,
+}
+impl Palette {
+    /// Read every palette in a PLAYPAL lump. The first one is the
+    /// normal palette, the rest are tinted for damage and pickups.
+    pub fn from_playpal(bytes: &[u8]) -> Result<Vec<Palette>> {
+        let stride = PALETTE_SIZE * 3;
+        if bytes.is_empty() || !bytes.len().is_multiple_of(stride) {
+            return Err(anyhow!("PLAYPAL has {} bytes, which is not a whole number of palettes.", bytes.len()));
+        }
+        Ok(bytes
+            .chunks_exact(stride)
+            .map(|palette| Palette {
+                colours: palette.chunks_exact(3).map(|rgb| [rgb[0], rgb[1], rgb[2]]).collect(),
+            })
+            .collect())
+    }
+
+    /// The opaque RGBA colour of a palette index.
+    pub fn rgba(&self, index: u8) -> [u8; 4] {
+        let [r, g, b] = self.colours[index as usize];
+        [r, g, b, 255]
+    }
+}
+
+/// The light tables from a COLORMAP lump. Each map says which
+/// palette index to draw in place of every other index, from
+/// full brightness at map 0 down to black.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ColourMaps {
+    pub maps: Vec<[u8; PALETTE_SIZE]>,
+}
+impl ColourMaps {
+    /// Read every map in a COLORMAP lump.
+    pub fn from_colormap(bytes: &[u8]) -> Result<Self> {
+        if bytes.is_empty() || !bytes.len().is_multiple_of(PALETTE_SIZE) {
+            return Err(anyhow!("COLORMAP has {} bytes, which is not a whole number of maps.", bytes.len()));
+        }
+        let maps = bytes
+            .chunks_exact(PALETTE_SIZE)
+            .map(|map| map.try_into().unwrap())
+            .collect();
+        Ok(ColourMaps { maps })
+    }
+
+    /// The palette index to draw for `index` using a certain map.
+    pub fn map(&self, map: usize, index: u8) -> u8 {
+        self.maps[map][index as usize]
+    }
+}
+
+/// A palette indexed picture, where `None` is transparent.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Picture {
+    pub width: u32,
+    pub height: u32,
+    /// How far the picture is drawn left of and above its origin.
+    pub left_offset: i32,
+    pub top_offset: i32,
+    pub pixels: Vec<Option<u8>>,
+}
+impl Picture {
+    /// Create a fully transparent picture.
+    pub fn new(width: u32, height: u32) -> Self {
+        Picture {
+            width,
+            height,
+            left_offset: 0,
+            top_offset: 0,
+            pixels: vec![None; width as usize * height as usize],
+        }
+    }
+
+    /// Decode a picture in the patch format, as used by wall
+    /// patches and sprites. Posts that start at or above the last
+    /// post are taken as relative, so tall patches decode too.
+    pub fn from_patch(bytes: &[u8]) -> Result<Self> {
+        let width = u16_at(bytes, 0)? as u32;
+        let height = u16_at(bytes, 2)? as u32;
+        // Check the size before allocating for it
+        if 8 + width as usize * 4 > bytes.len() {
+            return Err(anyhow!("Patch has {width} columns but the lump ends first."));
+        }
+        if width as usize * height as usize > bytes.len() * MAX_PATCH_PIXELS_PER_BYTE {
+            return Err(anyhow!("Patch is {width}x{height}, too big for a lump of {} bytes.", bytes.len()));
+        }
+        let mut picture = Picture::new(width, height);
+        picture.left_offset = i16_at(bytes, 4)? as i32;
+        picture.top_offset = i16_at(bytes, 6)? as i32;
+
+        for x in 0..width {
+            let mut offset = u32_at(bytes, 8 + x as usize * 4)? as usize;
+            let mut last_top: Option<u32> = None;
+            loop {
+                let top_delta = byte_at(bytes, offset)?;
+                if top_delta == 0xFF {
+                    break;
+                }
+                let top = match last_top {
+                    Some(last) if top_delta as u32 <= last => last + top_delta as u32,
+                    _ => top_delta as u32,
+                };
+                last_top = Some(top);
+
+                let length = byte_at(bytes, offset + 1)? as usize;
+                // Skip the length and the unused byte before the pixels
+                let start = offset + 3;
+                let column = bytes
+                    .get(start..start + length)
+                    .ok_or_else(|| anyhow!("Patch post runs past the end of the lump."))?;
+                for (i, index) in column.iter().enumerate() {
+                    let y = top + i as u32;
+                    if y < height {
+                        picture.pixels[(y * width + x) as usize] = Some(*index);
+                    }
+                }
+                // Then the pixels and the unused byte after them
+                offset = start + length + 1;
+            }
+        }
+        Ok(picture)
+    }
+
+    /// Decode a raw flat, which is 64x64 indices with no header.
+    pub fn from_flat(bytes: &[u8]) -> Result<Self> {
+        let size = (FLAT_SIZE * FLAT_SIZE) as usize;
+        if bytes.len() < size {
+            return Err(anyhow!("Flat has {} bytes but needs {size}.", bytes.len()));
+        }
+        let mut picture = Picture::new(FLAT_SIZE, FLAT_SIZE);
+        picture.pixels = bytes[..size].iter().map(|index| Some(*index)).collect();
+        Ok(picture)
+    }
+
+    /// Copy the opaque pixels of another picture onto this one with
+    /// its top left at `x`, `y`, clipping anything outside.
+    pub fn draw(&mut self, other: &Picture, x: i32, y: i32) {
+        for other_y in 0..other.height as i32 {
+            for other_x in 0..other.width as i32 {
+                let (dest_x, dest_y) = (x + other_x, y + other_y);
+                if dest_x < 0 || dest_y < 0 || dest_x >= self.width as i32 || dest_y >= self.height as i32 {
+                    continue;
+                }
+                if let Some(index) = other.pixels[(other_y * other.width as i32 + other_x) as usize] {
+                    self.pixels[(dest_y * self.width as i32 + dest_x) as usize] = Some(index);
+                }
+            }
+        }
+    }
+
+    /// Turn the picture into RGBA with a palette. Transparent
+    /// pixels become fully transparent black.
+    pub fn to_image(&self, name: &str, palette: &Palette) -> ImageData {
+        let pixels = self
+            .pixels
+            .iter()
+            .flat_map(|index| match index {
+                Some(index) => palette.rgba(*index),
+                None => [0; 4],
+            })
+            .collect();
+        ImageData {
+            name: name.to_owned(),
+            width: self.width,
+            height: self.height,
+            pixels,
+        }
+    }
+}
+
+/// Where a patch is placed within a wall texture.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PatchPlacement {
+    pub x: i32,
+    pub y: i32,
+    /// Index into the PNAMES list.
+    pub patch: usize,
+}
+
+/// A wall texture from a TEXTURE1 or TEXTURE2 lump.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TextureDef {
+    pub name: String,
+    pub width: u32,
+    pub height: u32,
+    pub patches: Vec<PatchPlacement>,
+}
+impl TextureDef {
+    /// Read every texture in a TEXTURE1 or TEXTURE2 lump.
+    pub fn from_texture_lump(bytes: &[u8]) -> Result<Vec<TextureDef>> {
+        let count = u32_at(bytes, 0)? as usize;
+        let mut textures = vec![];
+        for i in 0..count {
+            let offset = u32_at(bytes, 4 + i * 4)? as usize;
+            let name = name_at(bytes, offset)?;
+            // Skip the masked flag after the name
+            let width = u16_at(bytes, offset + 12)? as u32;
+            let height = u16_at(bytes, offset + 14)? as u32;
+            // Skip the unused column directory
+            let patch_count = u16_at(bytes, offset + 20)? as usize;
+
+            let mut patches = vec![];
+            for p in 0..patch_count {
+                let patch = offset + 22 + p * 10;
+                patches.push(PatchPlacement {
+                    x: i16_at(bytes, patch)? as i32,
+                    y: i16_at(bytes, patch + 2)? as i32,
+                    patch: u16_at(bytes, patch + 4)? as usize,
+                });
+            }
+            textures.push(TextureDef {
+                name,
+                width,
+                height,
+                patches,
+            });
+        }
+        Ok(textures)
+    }
+
+    /// Build the texture by drawing its patches in order.
+    /// `patches` holds the decoded patches by lump name. Fails if
+    /// the texture has no pixels or is bigger than `MAX_TEXTURE_SIZE`.
+    pub fn compose(&self, pnames: &[String], patches: &HashMap<String, Picture>) -> Result<Picture> {
+        if self.width == 0 || self.height == 0 || self.width > MAX_TEXTURE_SIZE || self.height > MAX_TEXTURE_SIZE {
+            return Err(anyhow!(
+                "Texture {} is {}x{}, which is not between 1 and {MAX_TEXTURE_SIZE} pixels on each side.",
+                self.name,
+                self.width,
+                self.height
+            ));
+        }
+        let mut picture = Picture::new(self.width, self.height);
+        for placement in &self.patches {
+            let patch_name = pnames.get(placement.patch).ok_or_else(|| {
+                anyhow!("Texture {} uses patch {} which is not in PNAMES.", self.name, placement.patch)
+            })?;
+            let patch = patches
+                .get(patch_name)
+                .ok_or_else(|| anyhow!("Texture {} uses missing patch {patch_name}.", self.name))?;
+            picture.draw(patch, placement.x, placement.y);
+        }
+        Ok(picture)
+    }
+}
+
+/// Read the patch names from a PNAMES lump, in upper case.
+pub fn parse_pnames(bytes: &[u8]) -> Result<Vec<String>> {
+    let count = u32_at(bytes, 0)? as usize;
+    (0..count).map(|i| name_at(bytes, 4 + i * 8)).collect()
+}
+
+/// Build every wall texture in a TEXTURE1 or TEXTURE2 lump as an
+/// RGBA image named after the texture. `patch_lumps` holds the
+/// bytes of the patch lumps by name. Patches that fail to decode
+/// and textures that fail to build are left out, along with the
+/// reason.
+pub fn compose_textures(
+    texture_lump: &[u8],
+    pnames_lump: &[u8],
+    patch_lumps: &HashMap<String, Vec<u8>>,
+    palette: &Palette,
+) -> Result<(Vec<ImageData>, Vec<anyhow::Error>)> {
+    let pnames = parse_pnames(pnames_lump)?;
+    let defs = TextureDef::from_texture_lump(texture_lump)?;
+    let mut errors = vec![];
+
+    let mut patches = HashMap::new();
+    for name in &pnames {
+        let lump = patch_lumps
+            .iter()
+            .find(|(lump_name, _)| lump_name.eq_ignore_ascii_case(name))
+            .map(|(_, lump)| lump);
+        match lump.map(|lump| Picture::from_patch(lump)) {
+            Some(Ok(patch)) => {
+                patches.insert(name.clone(), patch);
+            }
+            Some(Err(e)) => errors.push(e.context(format!("Failed to decode patch {name}"))),
+            // Textures that use it report it as missing
+            None => (),
+        }
+    }
+
+    let mut images = vec![];
+    for def in defs {
+        match def.compose(&pnames, &patches) {
+            Ok(picture) => images.push(picture.to_image(&def.name, palette)),
+            Err(e) => errors.push(e),
+        }
+    }
+    Ok((images, errors))
+}
+
+fn byte_at(bytes: &[u8], offset: usize) -> Result<u8> {
+    bytes
+        .get(offset)
+        .copied()
+        .ok_or_else(|| anyhow!("Lump ends before offset {offset}."))
+}
+
+fn array_at<const N: usize>(bytes: &[u8], offset: usize) -> Result<[u8; N]> {
+    bytes
+        .get(offset..offset + N)
+        .map(|slice| slice.try_into().unwrap())
+        .ok_or_else(|| anyhow!("Lump ends before offset {}.", offset + N))
+}
+
+fn u16_at(bytes: &[u8], offset: usize) -> Result<u16> {
+    Ok(u16::from_le_bytes(array_at(bytes, offset)?))
+}
+
+fn i16_at(bytes: &[u8], offset: usize) -> Result<i16> {
+    Ok(i16::from_le_bytes(array_at(bytes, offset)?))
+}
+
+fn u32_at(bytes: &[u8], offset: usize) -> Result<u32> {
+    Ok(u32::from_le_bytes(array_at(bytes, offset)?))
+}
+
+/// An eight byte lump name, padded with nulls, in upper case.
+fn name_at(bytes: &[u8], offset: usize) -> Result<String> {
+    let name: [u8; 8] = array_at(bytes, offset)?;
+    let end = name.iter().position(|byte| *byte == 0).unwrap_or(8);
+    Ok(String::from_utf8_lossy(&name[..end]).to_ascii_uppercase())
+}
+
+#[cfg(test)]
+pub mod tests {
+    use crate::doom::*;
+
+    /// A grey ramp palette, where index `i` is `(i, i, i)`.
+    pub fn palette() -> Palette {
+        let bytes: Vec<u8> = (0..=255u8).flat_map(|i| [i, i, i]).collect();
+        Palette::from_playpal(&bytes).unwrap().remove(0)
+    }
+
+    /// Build a patch lump from columns of `(top_delta, indices)` posts.
+    fn patch_lump(height: u16, columns: &[Vec<(u8, Vec<u8>)>]) -> Vec<u8> {
+        let mut bytes = vec![];
+        bytes.extend((columns.len() as u16).to_le_bytes());
+        bytes.extend(height.to_le_bytes());
+        bytes.extend(1i16.to_le_bytes());
+        bytes.extend(2i16.to_le_bytes());
+        let mut data = vec![];
+        let start = 8 + columns.len() * 4;
+        for column in columns {
+            bytes.extend(((start + data.len()) as u32).to_le_bytes());
+            for (top_delta, indices) in column {
+                data.extend([*top_delta, indices.len() as u8, 0]);
+                data.extend(indices);
+                data.push(0);
+            }
+            data.push(0xFF);
+        }
+        bytes.extend(data);
+        bytes
+    }
+
+    fn name(name: &str) -> [u8; 8] {
+        let mut bytes = [0; 8];
+        bytes[..name.len()].copy_from_slice(name.as_bytes());
+        bytes
+    }
+
+    #[test]
+    fn playpal_and_colormap() {
+        let mut playpal = vec![0; 768 * 2];
+        playpal[768 + 3..768 + 6].copy_from_slice(&[10, 20, 30]);
+        let palettes = Palette::from_playpal(&playpal).unwrap();
+        assert!(palettes.len() == 2);
+        assert!(palettes[1].rgba(1) == [10, 20, 30, 255]);
+        assert!(Palette::from_playpal(&[0; 100]).is_err());
+
+        let mut colormap = vec![0; 256 * 2];
+        colormap[256 + 5] = 7;
+        let maps = ColourMaps::from_colormap(&colormap).unwrap();
+        assert!(maps.maps.len() == 2);
+        assert!(maps.map(1, 5) == 7);
+    }
+
+    #[test]
+    fn flat() {
+        let bytes: Vec<u8> = (0..4096).map(|i| (i % 256) as u8).collect();
+        let image = Picture::from_flat(&bytes).unwrap().to_image("FLOOR", &palette());
+        assert!(image.width == 64 && image.height == 64);
+        assert!(image.pixel(3, 0) == [3, 3, 3, 255]);
+        assert!(Picture::from_flat(&[0; 10]).is_err());
+    }
+
+    #[test]
+    fn patch() {
+        let lump = patch_lump(4, &[vec![(0, vec![1, 2])], vec![(1, vec![3]), (3, vec![4])]]);
+        let picture = Picture::from_patch(&lump).unwrap();
+        assert!(picture.width == 2 && picture.height == 4);
+        assert!(picture.left_offset == 1 && picture.top_offset == 2);
+        let column = |x: usize| -> Vec<Option<u8>> { (0..4).map(|y| picture.pixels[y * 2 + x]).collect() };
+        assert!(column(0) == vec![Some(1), Some(2), None, None]);
+        assert!(column(1) == vec![None, Some(3), None, Some(4)]);
+        assert!(Picture::from_patch(&lump[..10]).is_err());
+
+        // A header claiming a huge picture in a tiny lump
+        let mut huge = lump.clone();
+        huge[0..4].copy_from_slice(&[1, 0, 0xFF, 0xFF]);
+        assert!(Picture::from_patch(&huge).is_err());
+        huge[0..4].copy_from_slice(&[0xFF, 0xFF, 1, 0]);
+        assert!(Picture::from_patch(&huge).is_err());
+    }
+
+    #[test]
+    fn tall_patch() {
+        // The second post starts at or above the first, so it is relative
+        let lump = patch_lump(6, &[vec![(2, vec![1]), (2, vec![2])]]);
+        let picture = Picture::from_patch(&lump).unwrap();
+        assert!(picture.pixels[2] == Some(1));
+        assert!(picture.pixels[4] == Some(2));
+    }
+
+    #[test]
+    fn compose() {
+        let mut pnames = 2u32.to_le_bytes().to_vec();
+        pnames.extend(name("wall00"));
+        pnames.extend(name("WALL01"));
+
+        // One 4x2 texture, made of patch 1 then patch 0 overlapping it
+        let mut texture1 = 1u32.to_le_bytes().to_vec();
+        texture1.extend(8u32.to_le_bytes());
+        texture1.extend(name("BRICK"));
+        texture1.extend(0u32.to_le_bytes());
+        texture1.extend(4u16.to_le_bytes());
+        texture1.extend(2u16.to_le_bytes());
+        texture1.extend(0u32.to_le_bytes());
+        texture1.extend(2u16.to_le_bytes());
+        for (x, patch) in [(0i16, 1u16), (3, 0)] {
+            texture1.extend(x.to_le_bytes());
+            texture1.extend(0i16.to_le_bytes());
+            texture1.extend(patch.to_le_bytes());
+            texture1.extend([0; 4]);
+        }
+
+        let mut lumps = HashMap::new();
+        lumps.insert("WALL00".to_owned(), patch_lump(2, &[vec![(0, vec![9, 9])], vec![(0, vec![9, 9])]]));
+        lumps.insert("wall01".to_owned(), patch_lump(2, &vec![vec![(0, vec![5, 6])]; 4]));
+
+        let (images, errors) = compose_textures(&texture1, &pnames, &lumps, &palette()).unwrap();
+        assert!(errors.is_empty());
+        let brick = &images[0];
+        assert!(brick.name == "BRICK" && brick.width == 4 && brick.height == 2);
+        assert!(brick.pixel(0, 1) == [6, 6, 6, 255]);
+        assert!(brick.pixel(3, 0) == [9, 9, 9, 255]);
+
+        lumps.remove("WALL00");
+        let (images, errors) = compose_textures(&texture1, &pnames, &lumps, &palette()).unwrap();
+        assert!(images.is_empty() && errors.len() == 1);
+
+        // Sizes are checked before anything is allocated
+        for (width, height) in [(65535, 65535), (0, 2)] {
+            let texture = TextureDef { name: "HUGE".to_owned(), width, height, patches: vec![] };
+            assert!(texture.compose(&[], &HashMap::new()).is_err());
+        }
+
+        // A broken patch is reported, as well as the texture using it
+        lumps.insert("WALL00".to_owned(), vec![2, 0]);
+        let (images, errors) = compose_textures(&texture1, &pnames, &lumps, &palette()).unwrap();
+        assert!(images.is_empty() && errors.len() == 2);
+        assert!(errors[0].to_string() == "Failed to decode patch WALL00");
+    }
+}
@@ -0,0 +1,32 @@
+//! Uploading of CPU side images to the GPU. This is kept as
+//! thin as possible so that everything else can be tested
+//! without a graphics context.
+
+use anyhow::{anyhow, Result};
+use notan::app::Graphics;
+use notan::prelude::{Texture, TextureBuilder, TextureFilter};
+use crate::image_data::ImageData;
+use crate::manifest::FilterMode;
+
+/// Upload an image to the GPU as a texture with the default filtering.
+pub fn upload(gfx: &mut Graphics, image: &ImageData) -> Result<Texture> {
+    upload_filtered(gfx, image, FilterMode::default())
+}
+
+/// Upload an image to the GPU as a texture with a certain filtering.
+/// notan cannot take mip levels from us, so trilinear textures have
+/// their mipmaps made by the driver. `mipmap::mip_chain` gives the
+/// same levels on the CPU for anything that samples them itself.
+pub fn upload_filtered(gfx: &mut Graphics, image: &ImageData, filter: FilterMode) -> Result<Texture> {
+    let builder = TextureBuilder::new(gfx).from_bytes(&image.pixels, image.width, image.height);
+    let builder = match filter {
+        FilterMode::Nearest => builder.with_filter(TextureFilter::Nearest, TextureFilter::Nearest),
+        FilterMode::Linear => builder.with_filter(TextureFilter::Linear, TextureFilter::Linear),
+        FilterMode::Trilinear => builder
+            .with_filter(TextureFilter::Linear, TextureFilter::Linear)
+            .with_mipmaps(true),
+    };
+    builder
+        .build()
+        .map_err(|e| anyhow!("Failed to upload texture {}: {e}", image.name))
+}
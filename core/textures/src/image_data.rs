@@ -0,0 +1,243 @@
+//! CPU side images. Everything in here works without a
+//! graphics context, so it can be used by tools and tested
+//! headless. Uploading to the GPU is done separately in `gpu`.
+
+use anyhow::{anyhow, Result};
+use image::ImageFormat as DecoderFormat;
+use std::fs::read;
+use std::path::Path;
+use vfs::Vfs;
+
+/// The image file formats that can be decoded.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ImageFormat {
+    Png,
+    Tga,
+    Bmp,
+}
+impl ImageFormat {
+    /// Work out the format from a file extension, ignoring case.
+    pub fn from_extension(extension: &str) -> Option<Self> {
+        match extension.to_ascii_lowercase().as_str() {
+            "png" => Some(ImageFormat::Png),
+            "tga" => Some(ImageFormat::Tga),
+            "bmp" => Some(ImageFormat::Bmp),
+            _ => None,
+        }
+    }
+
+    /// Work out the format from the extension of a path.
+    pub fn from_path(path: &Path) -> Option<Self> {
+        path.extension()
+            .and_then(|extension| extension.to_str())
+            .and_then(Self::from_extension)
+    }
+
+    fn decoder_format(self) -> DecoderFormat {
+        match self {
+            ImageFormat::Png => DecoderFormat::Png,
+            ImageFormat::Tga => DecoderFormat::Tga,
+            ImageFormat::Bmp => DecoderFormat::Bmp,
+        }
+    }
+}
+
+/// A named image held in memory as tightly packed RGBA8
+/// pixels, row by row from the top left.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ImageData {
+    pub name: String,
+    pub width: u32,
+    pub height: u32,
+    pub pixels: Vec<u8>,
+}
+impl ImageData {
+    /// Create an image from RGBA8 pixels, checking that it is at
+    /// least one pixel in size and that there are the right number
+    /// of bytes for the size.
+    pub fn new(name: &str, width: u32, height: u32, pixels: Vec<u8>) -> Result<Self> {
+        if width == 0 || height == 0 {
+            return Err(anyhow!("Image {name} is {width}x{height}, which has no pixels."));
+        }
+        let expected = width as usize * height as usize * 4;
+        if pixels.len() != expected {
+            return Err(anyhow!(
+                "Image {name} has {} bytes of pixels but {width}x{height} needs {expected}.",
+                pixels.len()
+            ));
+        }
+        Ok(ImageData {
+            name: name.to_owned(),
+            width,
+            height,
+            pixels,
+        })
+    }
+
+    /// Create an image where every pixel is the same colour,
+    /// checking the size in the same way as `new`.
+    pub fn filled(name: &str, width: u32, height: u32, colour: [u8; 4]) -> Result<Self> {
+        Self::new(name, width, height, colour.repeat(width as usize * height as usize))
+    }
+
+    /// Decode an image from the bytes of a file, working out the
+    /// format from its contents. TGA files cannot be recognised this
+    /// way, so use `decode_as` for those.
+    pub fn decode(name: &str, bytes: &[u8]) -> Result<Self> {
+        let image = image::load_from_memory(bytes)
+            .map_err(|e| anyhow!("Failed to decode image {name}: {e}"))?;
+        Self::from_decoded(name, image)
+    }
+
+    /// Decode an image from the bytes of a file of a known format.
+    pub fn decode_as(name: &str, bytes: &[u8], format: ImageFormat) -> Result<Self> {
+        let image = image::load_from_memory_with_format(bytes, format.decoder_format())
+            .map_err(|e| anyhow!("Failed to decode image {name}: {e}"))?;
+        Self::from_decoded(name, image)
+    }
+
+    /// Read and decode an image file, naming it after the file name
+    /// without the extension.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        let name = path
+            .file_stem()
+            .and_then(|stem| stem.to_str())
+            .ok_or_else(|| anyhow!("Image path {} has no file name.", path.display()))?;
+        Self::load_named(name, path)
+    }
+
+    /// Read and decode an image file under a certain name.
+    pub fn load_named<P: AsRef<Path>>(name: &str, path: P) -> Result<Self> {
+        let path = path.as_ref();
+        let bytes = read(path)?;
+        match ImageFormat::from_path(path) {
+            Some(format) => Self::decode_as(name, &bytes, format),
+            None => Self::decode(name, &bytes),
+        }
+    }
+
+    /// Read and decode an image through the virtual filesystem
+    /// under a certain name.
+    pub fn load_vfs_named(name: &str, vfs: &Vfs, path: &str) -> Result<Self> {
+        let bytes = vfs.read(path)?;
+        match ImageFormat::from_path(Path::new(path)) {
+            Some(format) => Self::decode_as(name, &bytes, format),
+            None => Self::decode(name, &bytes),
+        }
+    }
+
+    /// The embedded missing texture, decoded.
+    pub fn missing() -> Self {
+        Self::decode_as(crate::MISSING_TEX_NAME, crate::MISSING_TEX, ImageFormat::Png)
+            .expect("Embedded missing texture failed to decode")
+    }
+
+    /// The colour of the pixel at `x`, `y`.
+    pub fn pixel(&self, x: u32, y: u32) -> [u8; 4] {
+        let i = self.index(x, y);
+        [self.pixels[i], self.pixels[i + 1], self.pixels[i + 2], self.pixels[i + 3]]
+    }
+
+    /// Set the colour of the pixel at `x`, `y`.
+    pub fn set_pixel(&mut self, x: u32, y: u32, colour: [u8; 4]) {
+        let i = self.index(x, y);
+        self.pixels[i..i + 4].copy_from_slice(&colour);
+    }
+
+    fn index(&self, x: u32, y: u32) -> usize {
+        assert!(x < self.width && y < self.height, "Pixel out of bounds");
+        (y as usize * self.width as usize + x as usize) * 4
+    }
+
+    fn from_decoded(name: &str, image: image::DynamicImage) -> Result<Self> {
+        let rgba = image.into_rgba8();
+        let (width, height) = rgba.dimensions();
+        Self::new(name, width, height, rgba.into_raw())
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use crate::image_data::*;
+    use image::{ImageOutputFormat, RgbaImage};
+    use std::io::Cursor;
+
+    /// A 2x2 image with a different colour in each corner.
+    pub fn init() -> ImageData {
+        ImageData::new(
+            "corners",
+            2,
+            2,
+            vec![
+                255, 0, 0, 255, 0, 255, 0, 255,
+                0, 0, 255, 255, 255, 255, 255, 128,
+            ],
+        )
+        .unwrap()
+    }
+
+    fn encode(image: &ImageData, format: ImageOutputFormat) -> Vec<u8> {
+        let buffer = RgbaImage::from_raw(image.width, image.height, image.pixels.clone()).unwrap();
+        let mut bytes = Cursor::new(vec![]);
+        buffer.write_to(&mut bytes, format).unwrap();
+        bytes.into_inner()
+    }
+
+    #[test]
+    fn new_checks_size() {
+        assert!(ImageData::new("bad", 2, 2, vec![0; 15]).is_err());
+        assert!(ImageData::new("empty", 0, 2, vec![]).is_err());
+        assert!(ImageData::filled("ok", 3, 2, [1, 2, 3, 4]).unwrap().pixels.len() == 24);
+        assert!(ImageData::filled("empty", 3, 0, [0; 4]).is_err());
+    }
+
+    #[test]
+    fn pixels() {
+        let mut image = init();
+        assert!(image.pixel(1, 0) == [0, 255, 0, 255]);
+        image.set_pixel(1, 0, [9, 9, 9, 9]);
+        assert!(image.pixel(1, 0) == [9, 9, 9, 9]);
+    }
+
+    #[test]
+    fn decode_png() {
+        let image = init();
+        let decoded = ImageData::decode("corners", &encode(&image, ImageOutputFormat::Png)).unwrap();
+        assert!(decoded == image);
+    }
+
+    #[test]
+    fn decode_tga() {
+        let image = init();
+        let bytes = encode(&image, ImageOutputFormat::Tga);
+        let decoded = ImageData::decode_as("corners", &bytes, ImageFormat::Tga).unwrap();
+        assert!(decoded == image);
+    }
+
+    #[test]
+    fn decode_bmp() {
+        let image = init();
+        let decoded = ImageData::decode("corners", &encode(&image, ImageOutputFormat::Bmp)).unwrap();
+        assert!(decoded == image);
+    }
+
+    #[test]
+    fn decode_garbage() {
+        assert!(ImageData::decode("garbage", b"not an image").is_err());
+    }
+
+    #[test]
+    fn missing() {
+        let missing = ImageData::missing();
+        assert!(missing.name == crate::MISSING_TEX_NAME);
+        assert!(missing.pixels.len() == (missing.width * missing.height * 4) as usize);
+    }
+
+    #[test]
+    fn formats() {
+        assert!(ImageFormat::from_path(Path::new("a/STARTAN3.PNG")) == Some(ImageFormat::Png));
+        assert!(ImageFormat::from_extension("tga") == Some(ImageFormat::Tga));
+        assert!(ImageFormat::from_extension("json").is_none());
+    }
+}
@@ -1,47 +1,366 @@
 //! This is the library for texture operations as well
 //! as the serialisation structures for the textures.
 
-/// Constant that represents the missing texture path relative
-/// to this file. The missing texture is used to represent when a
-/// texture is not found by the game engine.
-pub const MISSING_TEX_PATH: &str = "./assets/missingtexture.png";
+/// The name that the missing texture is registered under. The
+/// missing texture is used to represent when a texture is not
+/// found by the game engine. Like every texture name it fits in
+/// a `TextureName`.
+pub const MISSING_TEX_NAME: &str = "MISSING";
 
+/// The image data of the missing texture, embedded so that it is
+/// always available even if nothing can be loaded from disk.
+pub const MISSING_TEX: &[u8] = include_bytes!("./assets/missingtexture.png");
+
+use anyhow::Result;
+use level_geometry::geometry::TextureName;
 use notan::app::Graphics;
-use notan::prelude::{Texture, TextureBuilder};
-use std::collections::HashMap;
-// use std::fs::read;
+use notan::log;
+use notan::prelude::Texture;
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+use std::fs::read_dir;
+use std::path::{Path, PathBuf};
+use vfs::Vfs;
+
+pub mod animation;
+pub mod atlas;
+pub mod doom;
+pub mod gpu;
+pub mod image_data;
+pub mod lighting;
+pub mod manifest;
+pub mod mipmap;
+pub mod watch;
+
+use image_data::{ImageData, ImageFormat};
+use manifest::{TextureEntry, TextureManifest};
+use watch::TextureWatcher;
 
-#[allow(dead_code)]
+/// Registry of textures by name. Textures can be embedded in the
+/// binary or loaded from disk at runtime, and lookups of unknown
+/// names fall back to the missing texture.
 pub struct TextureLoader {
     pub textures: HashMap<String, Texture>,
+    /// The fallback for names that are not loaded. It is kept apart
+    /// from `textures` so that unloading or reloading a texture that
+    /// happens to share its name cannot take it away.
+    missing: Texture,
+    /// Where each texture loaded from disk came from, so that it
+    /// can be loaded again after being unloaded.
+    sources: HashMap<String, PathBuf>,
+    /// How many users each texture has, from `acquire` and `release`.
+    refs: HashMap<String, usize>,
+    /// Properties of the textures that came from a manifest.
+    entries: HashMap<String, TextureEntry>,
+    /// Watches a source directory for changed images, if enabled.
+    watcher: Option<TextureWatcher>,
+    /// Names that `get` has already warned about, so that a missing
+    /// texture drawn every frame is only reported once.
+    warned: RefCell<HashSet<String>>,
 }
 impl TextureLoader {
     pub fn new(gfx: &mut Graphics) -> Self {
-        let missingtex = TextureBuilder::new(gfx)
-            .from_image(
-                include_bytes!("./assets/missingtexture.png")
-            )
-            .build()
+        let missingtex = gpu::upload(gfx, &ImageData::missing())
             .expect("Texture Building Failed");
 
-        let rayme = TextureBuilder::new(gfx)
-            .from_image(
-                include_bytes!("./assets/rayme_logo.png")
-            )
-            .build()
-            .expect("Texture Building Failed");
+        let rayme = ImageData::decode_as(
+            "rayme",
+            include_bytes!("./assets/rayme_logo.png"),
+            ImageFormat::Png,
+        )
+        .and_then(|image| gpu::upload(gfx, &image))
+        .expect("Texture Building Failed");
 
         let mut textures = HashMap::new();
-        textures.insert("missingtex".to_owned(), missingtex);
+        textures.insert(MISSING_TEX_NAME.to_owned(), missingtex.clone());
         textures.insert("rayme".to_owned(), rayme);
 
-        TextureLoader { textures }
+        TextureLoader {
+            textures,
+            missing: missingtex,
+            sources: HashMap::new(),
+            refs: HashMap::new(),
+            entries: HashMap::new(),
+            watcher: None,
+            warned: RefCell::new(HashSet::new()),
+        }
+    }
+
+    /// Create a loader with every texture listed in a manifest.
+    pub fn from_manifest(gfx: &mut Graphics, manifest: &TextureManifest) -> Self {
+        let mut loader = Self::new(gfx);
+        loader.load_manifest(gfx, manifest);
+        loader
+    }
+
+    /// Load every texture listed in a manifest, keeping the properties
+    /// of each one. Textures that fail to load are logged and skipped,
+    /// so they fall back to the missing texture. Returns the names that
+    /// were loaded.
+    pub fn load_manifest(&mut self, gfx: &mut Graphics, manifest: &TextureManifest) -> Vec<String> {
+        let mut loaded = vec![];
+        for entry in &manifest.textures {
+            self.entries.insert(entry.name.to_string(), entry.clone());
+            match self.load_file(gfx, entry.name.as_str(), manifest.image_path(entry)) {
+                Ok(_) => loaded.push(entry.name.to_string()),
+                Err(e) => log::error!("Skipping texture {}: {e}", entry.name),
+            }
+        }
+        loaded
+    }
+
+    /// The manifest properties of a texture, if it came from a manifest.
+    pub fn entry(&self, name: &str) -> Option<&TextureEntry> {
+        self.entries.get(name)
+    }
+
+    /// Load an image file from disk and register it under `name`,
+    /// replacing any texture that already has that name.
+    pub fn load_file<P: AsRef<Path>>(&mut self, gfx: &mut Graphics, name: &str, path: P) -> Result<()> {
+        let path = path.as_ref();
+        let image = ImageData::load_named(name, path)?;
+        self.insert_image(gfx, &image)?;
+        self.sources.insert(name.to_owned(), path.to_path_buf());
+        Ok(())
+    }
+
+    /// Load an image through the virtual filesystem and register it
+    /// under `name`. Like embedded textures, these stay loaded
+    /// rather than being freed by `unload`.
+    pub fn load_vfs(&mut self, gfx: &mut Graphics, vfs: &Vfs, name: &str, path: &str) -> Result<()> {
+        let image = ImageData::load_vfs_named(name, vfs, path)?;
+        self.insert_image(gfx, &image)
+    }
+
+    /// Load every texture in a manifest read through the virtual
+    /// filesystem, in the same way as `load_manifest`.
+    pub fn load_manifest_vfs(&mut self, gfx: &mut Graphics, vfs: &Vfs, manifest: &TextureManifest) -> Vec<String> {
+        let mut loaded = vec![];
+        for entry in &manifest.textures {
+            self.entries.insert(entry.name.to_string(), entry.clone());
+            let result = manifest
+                .load_image_vfs(vfs, entry)
+                .and_then(|image| self.insert_image(gfx, &image));
+            match result {
+                Ok(_) => loaded.push(entry.name.to_string()),
+                Err(e) => log::error!("Skipping texture {}: {e}", entry.name),
+            }
+        }
+        loaded
+    }
+
+    /// Upload an image that is already in memory and register it
+    /// under its name, replacing any texture that already has that name.
+    /// Uses the filtering from the manifest if the texture is in one.
+    pub fn insert_image(&mut self, gfx: &mut Graphics, image: &ImageData) -> Result<()> {
+        let filter = self.entry(&image.name).map(|entry| entry.filter).unwrap_or_default();
+        let texture = gpu::upload_filtered(gfx, image, filter)?;
+        self.textures.insert(image.name.clone(), texture);
+        // Warn again if it goes missing later
+        self.warned.borrow_mut().remove(&image.name);
+        Ok(())
+    }
+
+    /// Upload several in-memory images, such as textures decoded
+    /// from a WAD, registering each under its name. Images that fail
+    /// to upload are logged and skipped. Returns the names that were
+    /// inserted.
+    pub fn insert_images(&mut self, gfx: &mut Graphics, images: &[ImageData]) -> Vec<String> {
+        let mut inserted = vec![];
+        for image in images {
+            match self.insert_image(gfx, image) {
+                Ok(_) => inserted.push(image.name.clone()),
+                Err(e) => log::error!("Skipping texture {}: {e}", image.name),
+            }
+        }
+        inserted
+    }
+
+    /// Load every image in a directory, naming each texture after
+    /// its file name without the extension. Images that fail to load,
+    /// or whose names are too long for a map to refer to, are logged
+    /// and skipped. Returns the names that were loaded.
+    pub fn load_dir<P: AsRef<Path>>(&mut self, gfx: &mut Graphics, dir: P) -> Result<Vec<String>> {
+        let mut paths: Vec<PathBuf> = read_dir(dir)?
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .filter(|path| is_texture_file(path))
+            .collect();
+        // Sort so that name clashes resolve the same way every time
+        paths.sort();
+
+        let mut loaded = vec![];
+        for path in paths {
+            let name = match path.file_stem().and_then(|stem| stem.to_str()) {
+                Some(name) => name.to_owned(),
+                None => continue,
+            };
+            if !is_texture_name(&name, &path) {
+                continue;
+            }
+            match self.load_file(gfx, &name, &path) {
+                Ok(_) => loaded.push(name),
+                Err(e) => log::error!("Skipping texture {}: {e}", path.display()),
+            }
+        }
+        Ok(loaded)
+    }
+
+    /// Start watching a directory so that `reload_changed` picks up
+    /// images that are saved into it. Replaces any earlier watch.
+    pub fn watch_dir<P: AsRef<Path>>(&mut self, dir: P) -> Result<()> {
+        self.watcher = Some(TextureWatcher::new(dir)?);
+        Ok(())
+    }
+
+    /// Stop watching for changed images.
+    pub fn unwatch(&mut self) {
+        self.watcher = None;
+    }
+
+    /// Decode and swap in every image in the watched directory that
+    /// changed since the last call. Textures keep their names, and
+    /// images that are removed or fail to decode fall back to the
+    /// missing texture until they are back. That holds even for an
+    /// image named after the missing texture, which only replaces
+    /// the texture registered under its name and never the fallback.
+    /// Returns the names that were reloaded.
+    pub fn reload_changed(&mut self, gfx: &mut Graphics) -> Vec<String> {
+        let changed = match self.watcher.as_mut().map(|watcher| watcher.poll_images()) {
+            None => return vec![],
+            Some(Ok(changed)) => changed,
+            Some(Err(e)) => {
+                log::error!("Failed to poll for changed textures: {e}");
+                return vec![];
+            }
+        };
+
+        let mut reloaded = vec![];
+        for change in changed {
+            // Textures from a manifest may not be named after their file
+            let name = match self.sources.iter().find(|(_, source)| **source == change.path) {
+                Some((name, _)) => name.clone(),
+                None if is_texture_name(&change.name, &change.path) => change.name,
+                None => continue,
+            };
+            let Some(image) = change.image else {
+                log::info!("Texture {name} was removed, using {MISSING_TEX_NAME}");
+                self.textures.remove(&name);
+                continue;
+            };
+            let result = image.and_then(|mut image| {
+                image.name = name.clone();
+                self.insert_image(gfx, &image)
+            });
+            match result {
+                Ok(_) => {
+                    log::info!("Reloaded texture {name}");
+                    reloaded.push(name.clone());
+                }
+                Err(e) => {
+                    log::error!("Failed to reload texture {name}, using {MISSING_TEX_NAME}: {e}");
+                    self.textures.remove(&name);
+                }
+            }
+            self.sources.insert(name, change.path);
+        }
+        reloaded
+    }
+
+    /// Get a texture by name, falling back to the missing texture
+    /// if there is no texture with that name. Each missing name is
+    /// only warned about the first time.
+    pub fn get(&self, name: &str) -> &Texture {
+        match self.textures.get(name) {
+            Some(texture) => texture,
+            None => {
+                if first_miss(&mut self.warned.borrow_mut(), name) {
+                    log::warn!("Texture {name} not found, using {MISSING_TEX_NAME}");
+                }
+                self.missing()
+            }
+        }
+    }
+
+    /// Returns whether a texture with this name is loaded.
+    pub fn contains(&self, name: &str) -> bool {
+        self.textures.contains_key(name)
+    }
+
+    /// The texture used when a texture cannot be found.
+    pub fn missing(&self) -> &Texture {
+        &self.missing
+    }
+
+    /// Take a reference to a texture, loading it again from disk
+    /// if it has been unloaded. Falls back to the missing texture
+    /// in the same way as `get`.
+    pub fn acquire(&mut self, gfx: &mut Graphics, name: &str) -> &Texture {
+        if !self.contains(name) {
+            if let Some(path) = self.sources.get(name).cloned() {
+                if let Err(e) = self.load_file(gfx, name, &path) {
+                    log::error!("Failed to reload texture {name}: {e}");
+                }
+            }
+        }
+        if self.contains(name) {
+            *self.refs.entry(name.to_owned()).or_insert(0) += 1;
+        }
+        self.get(name)
+    }
+
+    /// Give up a reference taken with `acquire`. Textures loaded from
+    /// disk are unloaded once nothing references them.
+    pub fn release(&mut self, name: &str) {
+        if let Some(count) = self.refs.get_mut(name) {
+            *count = count.saturating_sub(1);
+            if *count == 0 {
+                self.refs.remove(name);
+                self.unload(name);
+            }
+        }
+    }
+
+    /// The number of outstanding references to a texture.
+    pub fn ref_count(&self, name: &str) -> usize {
+        self.refs.get(name).copied().unwrap_or(0)
+    }
+
+    /// Free a texture that was loaded from disk. It can still be
+    /// loaded again by `acquire`. Embedded textures stay loaded.
+    pub fn unload(&mut self, name: &str) {
+        if self.sources.contains_key(name) {
+            self.textures.remove(name);
+        }
+    }
+}
+
+/// Record a name as missing, returning whether it is the first time.
+fn first_miss(warned: &mut HashSet<String>, name: &str) -> bool {
+    !warned.contains(name) && warned.insert(name.to_owned())
+}
+
+/// Returns whether a path is an image format that can be decoded.
+fn is_texture_file(path: &Path) -> bool {
+    ImageFormat::from_path(path).is_some()
+}
+
+/// Whether the image at `path` can be registered under `name`,
+/// warning if it cannot.
+fn is_texture_name(name: &str, path: &Path) -> bool {
+    match TextureName::new(name) {
+        Ok(_) => true,
+        Err(e) => {
+            log::warn!("Skipping texture {}: {e}", path.display());
+            false
+        }
     }
 }
 
 #[cfg(test)]
 mod tests {
-    // use super::*;
+    use super::*;
+    use notan::app::empty::EmptyBackend;
+    use notan::app::BackendSystem;
     // use notan::{draw::DrawConfig, prelude::*};
 
     // #[test]
@@ -56,4 +375,91 @@ mod tests {
     //         TextureLoader::new(gfx);
     //     }
     // }
+
+    /// Graphics that do not need a window or a gpu.
+    fn graphics() -> Graphics {
+        Graphics::new(EmptyBackend::new().unwrap().get_graphics_backend()).unwrap()
+    }
+
+    fn save_png(image: &ImageData, path: &Path) {
+        image::RgbaImage::from_raw(image.width, image.height, image.pixels.clone())
+            .unwrap()
+            .save(path)
+            .unwrap();
+    }
+
+    #[test]
+    fn unload_missing() {
+        let dir = tempfile::tempdir().unwrap();
+        save_png(&image_data::tests::init(), &dir.path().join("MISSING.png"));
+        let mut gfx = graphics();
+        let mut loader = TextureLoader::new(&mut gfx);
+        let fallback = loader.missing().id();
+        loader.load_dir(&mut gfx, dir.path()).unwrap();
+
+        // A texture on disk with the same name can be freed like any other
+        loader.acquire(&mut gfx, MISSING_TEX_NAME);
+        loader.release(MISSING_TEX_NAME);
+        assert!(!loader.contains(MISSING_TEX_NAME));
+        assert!(loader.get("STARTAN3").id() == fallback);
+    }
+
+    #[test]
+    fn long_names() {
+        let dir = tempfile::tempdir().unwrap();
+        save_png(&image_data::tests::init(), &dir.path().join("STARTAN3.png"));
+        save_png(&image_data::tests::init(), &dir.path().join("STARTAN3X.png"));
+        let mut gfx = graphics();
+        let mut loader = TextureLoader::new(&mut gfx);
+        assert!(loader.load_dir(&mut gfx, dir.path()).unwrap() == vec!["STARTAN3"]);
+        assert!(!loader.contains("STARTAN3X"));
+
+        loader.watch_dir(dir.path()).unwrap();
+        save_png(&image_data::tests::init(), &dir.path().join("BIGDOOR10.png"));
+        assert!(loader.reload_changed(&mut gfx).is_empty());
+        assert!(!loader.contains("BIGDOOR10"));
+    }
+
+    #[test]
+    fn reload_missing() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("MISSING.png");
+        save_png(&image_data::tests::init(), &path);
+        let mut gfx = graphics();
+        let mut loader = TextureLoader::new(&mut gfx);
+        let fallback = loader.missing().id();
+        loader.load_dir(&mut gfx, dir.path()).unwrap();
+        loader.watch_dir(dir.path()).unwrap();
+
+        std::fs::remove_file(&path).unwrap();
+        assert!(loader.reload_changed(&mut gfx).is_empty());
+        assert!(!loader.contains(MISSING_TEX_NAME));
+        assert!(loader.get("STARTAN3").id() == fallback);
+
+        std::fs::write(&path, "half saved").unwrap();
+        assert!(loader.reload_changed(&mut gfx).is_empty());
+        assert!(loader.get("STARTAN3").id() == fallback);
+    }
+
+    #[test]
+    fn texture_extensions() {
+        assert!(is_texture_file(Path::new("walls/STARTAN3.png")));
+        assert!(is_texture_file(Path::new("FLOOR4_8.PNG")));
+        assert!(is_texture_file(Path::new("SKY1.tga")));
+        assert!(!is_texture_file(Path::new("textures.json")));
+        assert!(!is_texture_file(Path::new("README")));
+    }
+
+    #[test]
+    fn warn_once() {
+        let mut warned = HashSet::new();
+        assert!(first_miss(&mut warned, "STARTAN3"));
+        assert!(!first_miss(&mut warned, "STARTAN3"));
+        assert!(first_miss(&mut warned, "FLOOR4_8"));
+    }
+
+    #[test]
+    fn missing_name() {
+        assert!(level_geometry::geometry::TextureName::new(MISSING_TEX_NAME).is_ok());
+    }
 }
@@ -0,0 +1,194 @@
+//! Diminishing light in the style of Doom's colormaps. A sector's
+//! light level and the distance from the camera pick one of a
+//! fixed number of light levels, and each level has a precomputed
+//! brightness. The same ramp drives the GPU path, as a strip
+//! texture the shader samples, and the CPU path, either on RGBA
+//! colours or through a colormap on palette indices.
+
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use crate::doom::{ColourMaps, Palette, PALETTE_SIZE};
+use crate::image_data::ImageData;
+
+/// The number of light levels, as in Doom's COLORMAP.
+pub const LIGHT_LEVELS: usize = 32;
+
+/// How quickly light fades with distance.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct LightFalloff {
+    /// The distance in world units over which light drops by
+    /// one level.
+    pub distance_per_level: f32,
+}
+impl Default for LightFalloff {
+    fn default() -> Self {
+        LightFalloff {
+            distance_per_level: 64f32,
+        }
+    }
+}
+
+/// The precomputed brightness of every light level, from level 0
+/// at full brightness down to the darkest.
+#[derive(Clone, Debug, PartialEq)]
+pub struct LightRamp {
+    pub falloff: LightFalloff,
+    brightness: Vec<f32>,
+}
+impl LightRamp {
+    /// Fails if there are no levels, or if light does not fade
+    /// over a positive distance.
+    pub fn new(levels: usize, falloff: LightFalloff) -> Result<Self> {
+        if levels == 0 {
+            return Err(anyhow!("A light ramp needs at least one level."));
+        }
+        if falloff.distance_per_level.is_nan() || falloff.distance_per_level <= 0f32 {
+            return Err(anyhow!(
+                "Light must fade over a positive distance, not {}.",
+                falloff.distance_per_level
+            ));
+        }
+        let brightness = (0..levels)
+            .map(|level| 1f32 - level as f32 / levels as f32)
+            .collect();
+        Ok(LightRamp { falloff, brightness })
+    }
+
+    /// The number of light levels in the ramp.
+    pub fn levels(&self) -> usize {
+        self.brightness.len()
+    }
+
+    /// The light level to draw with for a sector light level and a
+    /// distance from the camera. Dark sectors start further down
+    /// the ramp, and everything gets darker with distance.
+    pub fn level(&self, sector_light: u8, distance: f32) -> usize {
+        let last = self.levels() - 1;
+        let sector_darkness = (255 - sector_light) as f32 / 256f32 * self.levels() as f32;
+        let distance_darkness = distance.max(0f32) / self.falloff.distance_per_level;
+        ((sector_darkness + distance_darkness) as usize).min(last)
+    }
+
+    /// The brightness of a light level, between 0 and 1.
+    pub fn brightness(&self, level: usize) -> f32 {
+        self.brightness[level.min(self.levels() - 1)]
+    }
+
+    /// Light an RGBA colour on the CPU. Alpha is left alone.
+    pub fn light(&self, colour: [u8; 4], sector_light: u8, distance: f32) -> [u8; 4] {
+        let brightness = self.brightness(self.level(sector_light, distance));
+        let channel = |value: u8| (value as f32 * brightness).round() as u8;
+        [channel(colour[0]), channel(colour[1]), channel(colour[2]), colour[3]]
+    }
+
+    /// The ramp as a strip one pixel high and one pixel per level
+    /// wide, for the shader to sample with nearest filtering.
+    pub fn to_image(&self) -> ImageData {
+        let pixels = self
+            .brightness
+            .iter()
+            .flat_map(|brightness| {
+                let value = (brightness * 255f32).round() as u8;
+                [value, value, value, 255]
+            })
+            .collect();
+        ImageData {
+            name: "lightramp".to_owned(),
+            width: self.levels() as u32,
+            height: 1,
+            pixels,
+        }
+    }
+
+    /// Build a colormap for a palette, mapping every index to the
+    /// palette colour closest to it at each light level. This is
+    /// for the CPU renderer when it draws palette indexed images.
+    pub fn colour_maps(&self, palette: &Palette) -> ColourMaps {
+        let maps = self
+            .brightness
+            .iter()
+            .map(|brightness| {
+                let mut map = [0u8; PALETTE_SIZE];
+                for (index, colour) in palette.colours.iter().enumerate() {
+                    let target = colour.map(|value| value as f32 * brightness);
+                    map[index] = nearest(palette, target);
+                }
+                map
+            })
+            .collect();
+        ColourMaps { maps }
+    }
+}
+impl Default for LightRamp {
+    fn default() -> Self {
+        Self::new(LIGHT_LEVELS, LightFalloff::default()).expect("Default light ramp is valid")
+    }
+}
+
+/// The index of the palette colour nearest to `target`.
+fn nearest(palette: &Palette, target: [f32; 3]) -> u8 {
+    let distance = |colour: &[u8; 3]| -> f32 {
+        colour
+            .iter()
+            .zip(target)
+            .map(|(value, target)| (*value as f32 - target).powi(2))
+            .sum()
+    };
+    let mut best = (0, f32::MAX);
+    for (index, colour) in palette.colours.iter().enumerate() {
+        let distance = distance(colour);
+        if distance < best.1 {
+            best = (index, distance);
+        }
+    }
+    best.0 as u8
+}
+
+#[cfg(test)]
+pub mod tests {
+    use crate::lighting::*;
+    use crate::doom::tests::palette;
+
+    #[test]
+    fn levels() {
+        let ramp = LightRamp::default();
+        assert!(ramp.level(255, 0f32) == 0);
+        // Darker sectors and distant walls sit further down the ramp
+        assert!(ramp.level(127, 0f32) == 16);
+        assert!(ramp.level(255, 640f32) == 10);
+        assert!(ramp.level(127, 640f32) == 26);
+        assert!(ramp.level(0, 10000f32) == LIGHT_LEVELS - 1);
+    }
+
+    #[test]
+    fn invalid_ramps() {
+        assert!(LightRamp::new(0, LightFalloff::default()).is_err());
+        assert!(LightRamp::new(LIGHT_LEVELS, LightFalloff { distance_per_level: 0f32 }).is_err());
+        assert!(LightRamp::new(LIGHT_LEVELS, LightFalloff { distance_per_level: f32::NAN }).is_err());
+        assert!(LightRamp::new(1, LightFalloff::default()).unwrap().level(0, 1000f32) == 0);
+    }
+
+    #[test]
+    fn light_colour() {
+        let ramp = LightRamp::default();
+        assert!(ramp.light([200, 100, 50, 128], 255, 0f32) == [200, 100, 50, 128]);
+        assert!(ramp.light([200, 100, 50, 128], 127, 0f32) == [100, 50, 25, 128]);
+    }
+
+    #[test]
+    fn strip_image() {
+        let image = LightRamp::default().to_image();
+        assert!(image.width == LIGHT_LEVELS as u32 && image.height == 1);
+        assert!(image.pixel(0, 0) == [255, 255, 255, 255]);
+        assert!(image.pixel(16, 0) == [128, 128, 128, 255]);
+    }
+
+    #[test]
+    fn colour_maps() {
+        // With a grey ramp palette every colour has an exact match
+        let maps = LightRamp::default().colour_maps(&palette());
+        assert!(maps.maps.len() == LIGHT_LEVELS);
+        assert!(maps.map(0, 200) == 200);
+        assert!(maps.map(16, 200) == 100);
+    }
+}
@@ -0,0 +1,246 @@
+//! The texture manifest, a JSON file kept next to the textures
+//! that lists every texture along with its properties. Both the
+//! engine and the editor build their texture set from it.
+
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use std::fs::{read_to_string, write};
+use std::path::{Path, PathBuf};
+use level_geometry::geometry::TextureName;
+use vfs::Vfs;
+use crate::animation::AnimatedTexture;
+use crate::image_data::ImageData;
+
+/// The usual file name of a manifest within a texture directory.
+pub const MANIFEST_FILE_NAME: &str = "textures.json";
+
+/// What a texture is used for.
+#[derive(Clone, Copy, Default, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TextureKind {
+    #[default]
+    Wall,
+    Flat,
+    Sprite,
+}
+
+/// How a texture is sampled when drawn.
+#[derive(Clone, Copy, Default, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FilterMode {
+    /// Blocky pixels, for the retro look.
+    #[default]
+    Nearest,
+    /// Smoothed pixels without mipmaps.
+    Linear,
+    /// Smoothed pixels, blending between mipmaps.
+    Trilinear,
+}
+impl FilterMode {
+    /// Returns whether the texture is sampled from a mip chain.
+    pub fn uses_mipmaps(self) -> bool {
+        self == FilterMode::Trilinear
+    }
+}
+
+/// A single texture listed in a manifest.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct TextureEntry {
+    /// Checked when the manifest is parsed, so that every texture
+    /// can be put on a wall.
+    pub name: TextureName,
+    /// Path to the image, relative to the manifest.
+    pub path: PathBuf,
+    #[serde(default)]
+    pub kind: TextureKind,
+    /// World units per pixel.
+    #[serde(default = "default_scale")]
+    pub scale: f32,
+    #[serde(default)]
+    pub filter: FilterMode,
+    /// How the texture changes over time, if it does.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub animation: Option<AnimatedTexture>,
+    /// Whether the texture gives off its own light, so that it is
+    /// drawn at full brightness in any sector but still fades with
+    /// distance.
+    #[serde(default)]
+    pub emissive: bool,
+    /// Whether the texture ignores lighting altogether, both from
+    /// its sector and from distance.
+    #[serde(default)]
+    pub fullbright: bool,
+}
+
+impl TextureEntry {
+    /// Returns whether the texture has frames or scrolls.
+    pub fn is_animated(&self) -> bool {
+        self.animation.is_some()
+    }
+}
+
+fn default_scale() -> f32 {
+    1f32
+}
+
+/// A list of textures, along with the directory that their
+/// paths are relative to.
+#[derive(Clone, Default, Debug, PartialEq, Serialize, Deserialize)]
+pub struct TextureManifest {
+    /// The directory that the manifest was loaded from.
+    #[serde(skip)]
+    pub root: PathBuf,
+    pub textures: Vec<TextureEntry>,
+}
+impl TextureManifest {
+    /// Parse a manifest from JSON, with texture paths relative to `root`.
+    pub fn from_json<P: AsRef<Path>>(json: &str, root: P) -> Result<Self> {
+        let mut manifest: TextureManifest = serde_json::from_str(json)?;
+        manifest.root = root.as_ref().to_path_buf();
+
+        let mut names: Vec<&str> = manifest.textures.iter().map(|entry| entry.name.as_str()).collect();
+        names.sort();
+        if let Some(pair) = names.windows(2).find(|pair| pair[0] == pair[1]) {
+            return Err(anyhow!("Texture {} is listed in the manifest twice.", pair[0]));
+        }
+        Ok(manifest)
+    }
+
+    /// Read a manifest file. Texture paths are relative to the
+    /// directory that the file is in.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        let root = path.parent().unwrap_or(Path::new(""));
+        Self::from_json(&read_to_string(path)?, root)
+    }
+
+    /// Read a manifest through the virtual filesystem. Texture paths
+    /// are relative to the manifest's directory within it.
+    pub fn load_vfs(vfs: &Vfs, path: &str) -> Result<Self> {
+        let root = Path::new(path).parent().unwrap_or(Path::new(""));
+        Self::from_json(&vfs.read_to_string(path)?, root)
+    }
+
+    /// Serialise the manifest to pretty printed JSON.
+    pub fn to_json(&self) -> Result<String> {
+        Ok(serde_json::to_string_pretty(self)?)
+    }
+
+    /// Write the manifest to a file.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        write(path, self.to_json()?)?;
+        Ok(())
+    }
+
+    /// Get a texture entry by name.
+    pub fn get(&self, name: &str) -> Option<&TextureEntry> {
+        self.textures.iter().find(|entry| entry.name.as_str() == name)
+    }
+
+    /// The path of a texture's image on disk.
+    pub fn image_path(&self, entry: &TextureEntry) -> PathBuf {
+        self.root.join(&entry.path)
+    }
+
+    /// Decode the image of a texture entry, named after the entry.
+    pub fn load_image(&self, entry: &TextureEntry) -> Result<ImageData> {
+        ImageData::load_named(entry.name.as_str(), self.image_path(entry))
+    }
+
+    /// Decode the image of a texture entry through the virtual
+    /// filesystem, for manifests loaded with `load_vfs`.
+    pub fn load_image_vfs(&self, vfs: &Vfs, entry: &TextureEntry) -> Result<ImageData> {
+        let path = self.image_path(entry);
+        let path = path
+            .to_str()
+            .ok_or_else(|| anyhow!("Texture path {} is not valid UTF-8.", path.display()))?;
+        ImageData::load_vfs_named(entry.name.as_str(), vfs, path)
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use crate::manifest::*;
+
+    const JSON: &str = r#"{
+        "textures": [
+            { "name": "STARTAN3", "path": "walls/STARTAN3.png" },
+            {
+                "name": "NUKAGE1",
+                "path": "flats/NUKAGE1.png",
+                "kind": "flat",
+                "scale": 2.0,
+                "filter": "trilinear",
+                "animation": {
+                    "frames": [
+                        { "texture": "NUKAGE1", "duration": 0.25 },
+                        { "texture": "NUKAGE2", "duration": 0.25 }
+                    ],
+                    "scroll": [0.0, 0.5]
+                },
+                "fullbright": true
+            }
+        ]
+    }"#;
+
+    #[test]
+    fn parse() {
+        let manifest = TextureManifest::from_json(JSON, "assets").unwrap();
+        let startan = manifest.get("STARTAN3").unwrap();
+        assert!(startan.kind == TextureKind::Wall);
+        assert!(startan.scale == 1f32);
+        assert!(startan.filter == FilterMode::Nearest);
+        assert!(!startan.fullbright && !startan.is_animated());
+
+        let nukage = manifest.get("NUKAGE1").unwrap();
+        assert!(nukage.kind == TextureKind::Flat);
+        assert!(nukage.filter == FilterMode::Trilinear);
+        assert!(nukage.is_animated() && nukage.fullbright && !nukage.emissive);
+        assert!(nukage.animation.as_ref().unwrap().frames[1].texture.as_str() == "NUKAGE2");
+        assert!(manifest.image_path(nukage) == Path::new("assets/flats/NUKAGE1.png"));
+    }
+
+    #[test]
+    fn round_trip() {
+        let manifest = TextureManifest::from_json(JSON, "").unwrap();
+        assert!(TextureManifest::from_json(&manifest.to_json().unwrap(), "").unwrap() == manifest);
+    }
+
+    #[test]
+    fn load_vfs() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir_all(dir.path().join("textures/walls")).unwrap();
+        std::fs::write(dir.path().join("textures/textures.json"), JSON).unwrap();
+        let image = crate::image_data::tests::init();
+        image::RgbaImage::from_raw(image.width, image.height, image.pixels.clone())
+            .unwrap()
+            .save(dir.path().join("textures/walls/STARTAN3.png"))
+            .unwrap();
+
+        let mut vfs = Vfs::new();
+        vfs.mount_dir(dir.path()).unwrap();
+        let manifest = TextureManifest::load_vfs(&vfs, "textures/textures.json").unwrap();
+        let startan = manifest.load_image_vfs(&vfs, manifest.get("STARTAN3").unwrap()).unwrap();
+        assert!(startan.name == "STARTAN3" && startan.pixels == image.pixels);
+        assert!(manifest.load_image_vfs(&vfs, manifest.get("NUKAGE1").unwrap()).is_err());
+    }
+
+    #[test]
+    fn duplicate_names() {
+        let json = r#"{ "textures": [
+            { "name": "A", "path": "a.png" },
+            { "name": "A", "path": "b.png" }
+        ] }"#;
+        assert!(TextureManifest::from_json(json, "").is_err());
+    }
+
+    #[test]
+    fn long_names() {
+        let json = r#"{ "textures": [{ "name": "TOOLONGNAME", "path": "a.png" }] }"#;
+        assert!(TextureManifest::from_json(json, "").is_err());
+        let json = r#"{ "textures": [{ "name": "A", "path": "a.png", "animation": {
+            "frames": [{ "texture": "TOOLONGNAME", "duration": 1.0 }]
+        } }] }"#;
+        assert!(TextureManifest::from_json(json, "").is_err());
+    }
+}
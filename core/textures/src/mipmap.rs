@@ -0,0 +1,262 @@
+//! Generation of mip chains on the CPU, so that distant walls
+//! sample a smaller version of their texture instead of
+//! shimmering. Each level is half the size of the one before,
+//! down to 1x1.
+
+use serde::{Deserialize, Serialize};
+use std::f32::consts::PI;
+use crate::image_data::ImageData;
+
+/// The filter used to shrink each level into the next.
+#[derive(Clone, Copy, Default, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MipFilter {
+    /// Average of the pixels each new pixel covers. Fast and soft.
+    #[default]
+    Box,
+    /// Windowed sinc over two lobes. Sharper, but can ring
+    /// around hard edges.
+    Lanczos,
+}
+
+/// Settings for generating a mip chain.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MipOptions {
+    #[serde(default)]
+    pub filter: MipFilter,
+    /// Whether to filter in linear light rather than on the sRGB
+    /// values, which keeps smaller levels from getting darker.
+    #[serde(default = "default_gamma_correct")]
+    pub gamma_correct: bool,
+}
+impl Default for MipOptions {
+    fn default() -> Self {
+        MipOptions {
+            filter: MipFilter::default(),
+            gamma_correct: default_gamma_correct(),
+        }
+    }
+}
+
+fn default_gamma_correct() -> bool {
+    true
+}
+
+/// Build the mip chain of an image. The first level is the image
+/// itself and the last is 1x1. An image with no pixels has no
+/// smaller levels.
+pub fn mip_chain(image: &ImageData, options: &MipOptions) -> Vec<ImageData> {
+    let mut chain = vec![image.clone()];
+    while let Some(last) = chain.last() {
+        if last.width <= 1 && last.height <= 1 || last.width == 0 || last.height == 0 {
+            break;
+        }
+        let next = downsample(last, options);
+        chain.push(next);
+    }
+    chain
+}
+
+/// Shrink an image to half its size, rounding down but never
+/// below one pixel. An image with no pixels is returned as it is.
+pub fn downsample(image: &ImageData, options: &MipOptions) -> ImageData {
+    if image.width == 0 || image.height == 0 {
+        return image.clone();
+    }
+    let (width, height) = ((image.width / 2).max(1), (image.height / 2).max(1));
+    let pixels: Vec<[f32; 4]> = image
+        .pixels
+        .chunks_exact(4)
+        .map(|pixel| to_float(pixel, options.gamma_correct))
+        .collect();
+
+    // Filter the rows, then the columns of the result
+    let mut rows = Vec::with_capacity(width as usize * image.height as usize);
+    for row in pixels.chunks_exact(image.width as usize) {
+        rows.extend(resample(row, width as usize, options.filter));
+    }
+    let mut columns = vec![[0f32; 4]; width as usize * height as usize];
+    for x in 0..width as usize {
+        let column: Vec<[f32; 4]> = rows.iter().skip(x).step_by(width as usize).copied().collect();
+        for (y, pixel) in resample(&column, height as usize, options.filter).into_iter().enumerate() {
+            columns[y * width as usize + x] = pixel;
+        }
+    }
+
+    ImageData {
+        name: image.name.clone(),
+        width,
+        height,
+        pixels: columns
+            .iter()
+            .flat_map(|pixel| to_bytes(*pixel, options.gamma_correct))
+            .collect(),
+    }
+}
+
+/// Resample a line of pixels to a shorter length.
+fn resample(line: &[[f32; 4]], length: usize, filter: MipFilter) -> Vec<[f32; 4]> {
+    let scale = line.len() as f32 / length as f32;
+    (0..length)
+        .map(|i| {
+            let weights: Vec<(usize, f32)> = match filter {
+                MipFilter::Box => box_weights(i, scale, line.len()),
+                MipFilter::Lanczos => lanczos_weights(i, scale, line.len()),
+            };
+            let total: f32 = weights.iter().map(|(_, weight)| weight).sum();
+            let mut sum = [0f32; 4];
+            for (source, weight) in weights {
+                for (channel, value) in sum.iter_mut().zip(line[source]) {
+                    *channel += value * weight / total;
+                }
+            }
+            sum
+        })
+        .collect()
+}
+
+/// How much of each source pixel the new pixel `i` covers.
+fn box_weights(i: usize, scale: f32, len: usize) -> Vec<(usize, f32)> {
+    let (start, end) = (i as f32 * scale, (i + 1) as f32 * scale);
+    (start.floor() as usize..(end.ceil() as usize).min(len))
+        .map(|source| {
+            let overlap = end.min(source as f32 + 1.0) - start.max(source as f32);
+            (source, overlap)
+        })
+        .filter(|(_, overlap)| *overlap > 0.0)
+        .collect()
+}
+
+/// Lanczos weights of the source pixels around the new pixel `i`.
+/// Pixels past the edge repeat the edge pixel.
+fn lanczos_weights(i: usize, scale: f32, len: usize) -> Vec<(usize, f32)> {
+    const LOBES: f32 = 2.0;
+    let centre = (i as f32 + 0.5) * scale;
+    let first = (centre - LOBES * scale).floor() as i64;
+    let last = (centre + LOBES * scale).ceil() as i64;
+    (first..=last)
+        .map(|source| {
+            let distance = (source as f32 + 0.5 - centre) / scale;
+            (source.clamp(0, len as i64 - 1) as usize, lanczos(distance, LOBES))
+        })
+        .collect()
+}
+
+fn lanczos(x: f32, lobes: f32) -> f32 {
+    if x == 0.0 {
+        1.0
+    } else if x.abs() >= lobes {
+        0.0
+    } else {
+        let px = PI * x;
+        lobes * px.sin() * (px / lobes).sin() / (px * px)
+    }
+}
+
+fn to_float(pixel: &[u8], gamma_correct: bool) -> [f32; 4] {
+    let channel = |value: u8| {
+        let value = value as f32 / 255.0;
+        match gamma_correct {
+            true => srgb_to_linear(value),
+            false => value,
+        }
+    };
+    [channel(pixel[0]), channel(pixel[1]), channel(pixel[2]), pixel[3] as f32 / 255.0]
+}
+
+fn to_bytes(pixel: [f32; 4], gamma_correct: bool) -> [u8; 4] {
+    let channel = |value: f32| {
+        let value = match gamma_correct {
+            true => linear_to_srgb(value.max(0.0)),
+            false => value,
+        };
+        (value.clamp(0.0, 1.0) * 255.0).round() as u8
+    };
+    [channel(pixel[0]), channel(pixel[1]), channel(pixel[2]), (pixel[3].clamp(0.0, 1.0) * 255.0).round() as u8]
+}
+
+fn srgb_to_linear(value: f32) -> f32 {
+    if value <= 0.04045 {
+        value / 12.92
+    } else {
+        ((value + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+fn linear_to_srgb(value: f32) -> f32 {
+    if value <= 0.0031308 {
+        value * 12.92
+    } else {
+        1.055 * value.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use crate::mipmap::*;
+
+    /// A 2x2 checkerboard of black and white.
+    fn checker() -> ImageData {
+        let (black, white) = ([0, 0, 0, 255], [255, 255, 255, 255]);
+        ImageData::new("checker", 2, 2, [black, white, white, black].concat()).unwrap()
+    }
+
+    fn options(filter: MipFilter, gamma_correct: bool) -> MipOptions {
+        MipOptions { filter, gamma_correct }
+    }
+
+    #[test]
+    fn chain_sizes() {
+        let image = ImageData::filled("wall", 8, 2, [10, 20, 30, 255]).unwrap();
+        let chain = mip_chain(&image, &MipOptions::default());
+        let sizes: Vec<(u32, u32)> = chain.iter().map(|level| (level.width, level.height)).collect();
+        assert!(sizes == vec![(8, 2), (4, 1), (2, 1), (1, 1)]);
+        assert!(chain[0] == image);
+    }
+
+    #[test]
+    fn box_filter() {
+        let level = downsample(&checker(), &options(MipFilter::Box, false));
+        assert!(level.pixel(0, 0) == [128, 128, 128, 255]);
+    }
+
+    #[test]
+    fn gamma_correct_box_filter() {
+        // Half of the light of white is brighter than half of its value
+        let level = downsample(&checker(), &options(MipFilter::Box, true));
+        assert!(level.pixel(0, 0) == [188, 188, 188, 255]);
+    }
+
+    #[test]
+    fn empty_image() {
+        // Only possible by filling in the fields directly
+        let empty = ImageData {
+            name: "empty".to_owned(),
+            width: 0,
+            height: 4,
+            pixels: vec![],
+        };
+        assert!(downsample(&empty, &MipOptions::default()) == empty);
+        assert!(mip_chain(&empty, &MipOptions::default()) == vec![empty]);
+    }
+
+    #[test]
+    fn box_filter_odd_size() {
+        let image = ImageData::new("odd", 3, 1, [[0; 4], [90; 4], [180; 4]].concat()).unwrap();
+        let level = downsample(&image, &options(MipFilter::Box, false));
+        assert!(level.width == 1 && level.pixel(0, 0) == [90; 4]);
+    }
+
+    #[test]
+    fn lanczos_filter() {
+        let flat = ImageData::filled("flat", 8, 8, [200, 100, 50, 255]).unwrap();
+        for level in mip_chain(&flat, &options(MipFilter::Lanczos, true)) {
+            assert!(level.pixels.chunks_exact(4).all(|pixel| pixel == [200, 100, 50, 255]));
+        }
+
+        // Symmetric input gives a symmetric result
+        let ramp = ImageData::new("ramp", 4, 1, [[0; 4], [255; 4], [255; 4], [0; 4]].concat()).unwrap();
+        let level = downsample(&ramp, &options(MipFilter::Lanczos, false));
+        assert!(level.pixel(0, 0) == level.pixel(1, 0));
+    }
+}
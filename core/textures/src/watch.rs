@@ -0,0 +1,239 @@
+//! Polling of a texture directory for changed images, so that
+//! textures can be swapped in while the engine or editor is
+//! running. Polling needs no platform support and is cheap
+//! enough to do every second or so during development.
+
+use anyhow::Result;
+use notan::log;
+use std::collections::HashMap;
+use std::fs::{metadata, read_dir};
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+use crate::image_data::{ImageData, ImageFormat};
+
+/// A change to an image in a watched directory.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Change {
+    Added(PathBuf),
+    Modified(PathBuf),
+    Removed(PathBuf),
+}
+
+/// An image that was added, modified or removed, decoded again
+/// unless it was removed.
+#[derive(Debug)]
+pub struct ReloadedImage {
+    /// The file name without the extension.
+    pub name: String,
+    pub path: PathBuf,
+    /// The decoded image, or why it failed to decode. `None` if
+    /// the image was removed.
+    pub image: Option<Result<ImageData>>,
+}
+
+/// What is known about a file between polls.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct Stamp {
+    modified: SystemTime,
+    len: u64,
+}
+
+/// Watches the images in a directory and the directories inside
+/// it by comparing their modification times and sizes on every
+/// poll.
+#[derive(Clone, Debug)]
+pub struct TextureWatcher {
+    dir: PathBuf,
+    stamps: HashMap<PathBuf, Stamp>,
+}
+impl TextureWatcher {
+    /// Start watching a directory. Images that are already there
+    /// are not reported as added.
+    pub fn new<P: AsRef<Path>>(dir: P) -> Result<Self> {
+        let mut watcher = TextureWatcher {
+            dir: dir.as_ref().to_path_buf(),
+            stamps: HashMap::new(),
+        };
+        watcher.stamps = watcher.scan()?;
+        Ok(watcher)
+    }
+
+    /// The directory being watched.
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Look for images that were added, modified or removed since
+    /// the last poll, sorted by path.
+    pub fn poll(&mut self) -> Result<Vec<Change>> {
+        let stamps = self.scan()?;
+        let mut changes = vec![];
+        for (path, stamp) in &stamps {
+            match self.stamps.get(path) {
+                None => changes.push(Change::Added(path.clone())),
+                Some(old) if old != stamp => changes.push(Change::Modified(path.clone())),
+                Some(_) => (),
+            }
+        }
+        for path in self.stamps.keys() {
+            if !stamps.contains_key(path) {
+                changes.push(Change::Removed(path.clone()));
+            }
+        }
+        changes.sort_by(|a, b| change_path(a).cmp(change_path(b)));
+        self.stamps = stamps;
+        Ok(changes)
+    }
+
+    /// Poll, then decode every image that was added or modified.
+    /// Removed images are included without an image.
+    pub fn poll_images(&mut self) -> Result<Vec<ReloadedImage>> {
+        let mut images = vec![];
+        for change in self.poll()? {
+            let removed = matches!(change, Change::Removed(_));
+            let path = change_path(&change).to_path_buf();
+            if let Some(name) = path.file_stem().and_then(|stem| stem.to_str()) {
+                images.push(ReloadedImage {
+                    name: name.to_owned(),
+                    image: (!removed).then(|| ImageData::load_named(name, &path)),
+                    path,
+                });
+            }
+        }
+        Ok(images)
+    }
+
+    /// Stamp every image below the watched directory. Only the
+    /// watched directory itself failing to read is an error, and
+    /// anything below it that cannot be read is logged and left
+    /// out until it can.
+    fn scan(&self) -> Result<HashMap<PathBuf, Stamp>> {
+        let mut stamps = HashMap::new();
+        let mut dirs = vec![self.dir.clone()];
+        while let Some(dir) = dirs.pop() {
+            let entries = match read_dir(&dir) {
+                Ok(entries) => entries,
+                Err(e) if dir == self.dir => return Err(e.into()),
+                Err(e) => {
+                    log::warn!("Skipping texture directory {}: {e}", dir.display());
+                    continue;
+                }
+            };
+            for entry in entries {
+                let entry = match entry {
+                    Ok(entry) => entry,
+                    Err(e) => {
+                        log::warn!("Skipping an entry of {}: {e}", dir.display());
+                        continue;
+                    }
+                };
+                let path = entry.path();
+                // Links to directories are not followed, so they cannot loop
+                if entry.file_type().is_ok_and(|file_type| file_type.is_dir()) {
+                    dirs.push(path);
+                    continue;
+                }
+                if ImageFormat::from_path(&path).is_none() {
+                    continue;
+                }
+                // The file can go away between listing and reading it
+                let Ok(metadata) = metadata(&path) else {
+                    continue;
+                };
+                match metadata.modified() {
+                    Ok(modified) => {
+                        let stamp = Stamp {
+                            modified,
+                            len: metadata.len(),
+                        };
+                        stamps.insert(path, stamp);
+                    }
+                    Err(e) => log::warn!("Skipping texture {}: {e}", path.display()),
+                }
+            }
+        }
+        Ok(stamps)
+    }
+}
+
+fn change_path(change: &Change) -> &Path {
+    match change {
+        Change::Added(path) | Change::Modified(path) | Change::Removed(path) => path,
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use crate::watch::*;
+    use crate::image_data::tests::init;
+    use image::RgbaImage;
+    use std::fs::{create_dir, remove_file, write, File};
+    use std::time::Duration;
+
+    fn save_png(image: &ImageData, path: &Path) {
+        RgbaImage::from_raw(image.width, image.height, image.pixels.clone())
+            .unwrap()
+            .save(path)
+            .unwrap();
+    }
+
+    /// Push a file's modification time forward, since the clock may
+    /// not tick between two writes in a test.
+    fn touch(path: &Path) {
+        let later = SystemTime::now() + Duration::from_secs(10);
+        File::options().write(true).open(path).unwrap().set_modified(later).unwrap();
+    }
+
+    #[test]
+    fn changes() {
+        let dir = tempfile::tempdir().unwrap();
+        let wall = dir.path().join("WALL.png");
+        save_png(&init(), &wall);
+        write(dir.path().join("notes.txt"), "not an image").unwrap();
+
+        let mut watcher = TextureWatcher::new(dir.path()).unwrap();
+        assert!(watcher.poll().unwrap().is_empty());
+
+        let floor = dir.path().join("FLOOR.png");
+        save_png(&init(), &floor);
+        touch(&wall);
+        assert!(watcher.poll().unwrap() == vec![Change::Added(floor.clone()), Change::Modified(wall)]);
+        assert!(watcher.poll().unwrap().is_empty());
+
+        remove_file(&floor).unwrap();
+        assert!(watcher.poll().unwrap() == vec![Change::Removed(floor)]);
+    }
+
+    #[test]
+    fn subdirectories() {
+        let dir = tempfile::tempdir().unwrap();
+        let walls = dir.path().join("walls");
+        create_dir(&walls).unwrap();
+        let mut watcher = TextureWatcher::new(dir.path()).unwrap();
+
+        let startan = walls.join("STARTAN3.png");
+        save_png(&init(), &startan);
+        assert!(watcher.poll().unwrap() == vec![Change::Added(startan.clone())]);
+
+        remove_file(&startan).unwrap();
+        let images = watcher.poll_images().unwrap();
+        assert!(images.len() == 1);
+        assert!(images[0].name == "STARTAN3" && images[0].path == startan && images[0].image.is_none());
+    }
+
+    #[test]
+    fn poll_images() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut watcher = TextureWatcher::new(dir.path()).unwrap();
+
+        save_png(&init(), &dir.path().join("GOOD.png"));
+        write(dir.path().join("BAD.png"), "half saved").unwrap();
+        let images = watcher.poll_images().unwrap();
+
+        assert!(images.len() == 2);
+        assert!(images[0].name == "BAD" && images[0].image.as_ref().unwrap().is_err());
+        assert!(images[1].name == "GOOD");
+        let good = images[1].image.as_ref().unwrap().as_ref().unwrap();
+        assert!(good.name == "GOOD" && good.pixels == init().pixels);
+    }
+}
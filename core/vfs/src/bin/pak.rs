@@ -0,0 +1,41 @@
+//! Pack a directory of assets into a `.pak` archive, or list
+//! what is in one.
+//!
+//! ```text
+//! pak create <output.pak> <directory> [--compress]
+//! pak list <archive.pak>
+//! ```
+
+use anyhow::{anyhow, Result};
+use std::env::args;
+use vfs::pak::{Compression, PakReader, PakWriter};
+
+fn main() -> Result<()> {
+    let args: Vec<String> = args().skip(1).collect();
+    match args.iter().map(|arg| arg.as_str()).collect::<Vec<_>>().as_slice() {
+        ["create", output, dir, flags @ ..] => {
+            let compression = match flags {
+                [] => Compression::None,
+                ["--compress"] => Compression::Deflate,
+                _ => return Err(anyhow!("Unknown options {flags:?}")),
+            };
+            let mut writer = PakWriter::new();
+            let count = writer.add_dir(dir, compression)?;
+            writer.save(output)?;
+            println!("Packed {count} files into {output}");
+        }
+        ["list", archive] => {
+            let pak = PakReader::open(archive)?;
+            for path in pak.paths() {
+                let entry = pak.entry(path).unwrap();
+                println!("{:>10} {:>10} {path}", entry.size, entry.stored_size);
+            }
+        }
+        _ => {
+            eprintln!("usage: pak create <output.pak> <directory> [--compress]");
+            eprintln!("       pak list <archive.pak>");
+            std::process::exit(1);
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,193 @@
+//! A virtual filesystem that assets are loaded through. Pak
+//! archives and plain directories are mounted in order, and a
+//! file in a later mount hides the same path in earlier ones,
+//! so a mod is just one more mount on top of the base game.
+
+use anyhow::{anyhow, Result};
+use std::collections::BTreeSet;
+use std::fs::read;
+use std::path::{Path, PathBuf};
+
+pub mod pak;
+
+use pak::PakReader;
+
+/// Something mounted into the filesystem.
+#[derive(Clone, Debug)]
+pub enum Mount {
+    Dir(PathBuf),
+    Pak(PakReader),
+}
+impl Mount {
+    fn read(&self, path: &str) -> Option<Result<Vec<u8>>> {
+        match self {
+            Mount::Dir(dir) => {
+                let file = dir.join(path);
+                file.is_file().then(|| Ok(read(file)?))
+            }
+            Mount::Pak(pak) => pak.contains(path).then(|| pak.read(path)),
+        }
+    }
+
+    fn contains(&self, path: &str) -> bool {
+        match self {
+            Mount::Dir(dir) => dir.join(path).is_file(),
+            Mount::Pak(pak) => pak.contains(path),
+        }
+    }
+
+    fn paths(&self) -> Vec<String> {
+        match self {
+            Mount::Dir(dir) => {
+                let mut paths = vec![];
+                list_dir(dir, dir, &mut paths);
+                paths
+            }
+            Mount::Pak(pak) => pak.paths().map(|path| path.to_owned()).collect(),
+        }
+    }
+}
+
+/// The mounted archives and directories, lowest priority first.
+#[derive(Clone, Default, Debug)]
+pub struct Vfs {
+    mounts: Vec<Mount>,
+}
+impl Vfs {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Mount a directory above everything mounted so far.
+    pub fn mount_dir<P: AsRef<Path>>(&mut self, dir: P) -> Result<()> {
+        let dir = dir.as_ref();
+        if !dir.is_dir() {
+            return Err(anyhow!("{} is not a directory.", dir.display()));
+        }
+        self.mounts.push(Mount::Dir(dir.to_path_buf()));
+        Ok(())
+    }
+
+    /// Open and mount an archive above everything mounted so far.
+    pub fn mount_pak<P: AsRef<Path>>(&mut self, path: P) -> Result<()> {
+        self.mounts.push(Mount::Pak(PakReader::open(path)?));
+        Ok(())
+    }
+
+    /// The mounts, lowest priority first.
+    pub fn mounts(&self) -> &[Mount] {
+        &self.mounts
+    }
+
+    /// Read a file from the highest priority mount that has it.
+    pub fn read(&self, path: &str) -> Result<Vec<u8>> {
+        let path = normalise(path)?;
+        self.mounts
+            .iter()
+            .rev()
+            .find_map(|mount| mount.read(&path))
+            .unwrap_or_else(|| Err(anyhow!("No file {path} in any mount.")))
+    }
+
+    /// Read a file as UTF-8 text.
+    pub fn read_to_string(&self, path: &str) -> Result<String> {
+        Ok(String::from_utf8(self.read(path)?)?)
+    }
+
+    /// Returns whether any mount has a file at a path.
+    pub fn exists(&self, path: &str) -> bool {
+        match normalise(path) {
+            Ok(path) => self.mounts.iter().any(|mount| mount.contains(&path)),
+            Err(_) => false,
+        }
+    }
+
+    /// Every file path across all mounts that starts with `prefix`,
+    /// sorted and without duplicates. The prefix is normalised like
+    /// any other path, and an empty one lists everything.
+    pub fn list(&self, prefix: &str) -> Vec<String> {
+        let prefix = match normalise(prefix) {
+            // Keep a trailing separator so only that directory matches
+            Ok(normalised) if prefix.ends_with(['/', '\\']) => normalised + "/",
+            Ok(normalised) => normalised,
+            Err(_) if prefix.split(['/', '\\']).all(|part| matches!(part, "" | ".")) => String::new(),
+            Err(_) => return vec![],
+        };
+        let paths: BTreeSet<String> = self.mounts.iter().flat_map(|mount| mount.paths()).collect();
+        paths.into_iter().filter(|path| path.starts_with(&prefix)).collect()
+    }
+}
+
+/// Turn a path into the form used within the filesystem: relative,
+/// separated by `/`, and without `.` parts. Paths that climb out
+/// with `..` are rejected.
+pub fn normalise(path: &str) -> Result<String> {
+    let mut parts = vec![];
+    for part in path.split(['/', '\\']) {
+        match part {
+            "" | "." => (),
+            ".." => return Err(anyhow!("Path {path} leaves the filesystem.")),
+            part => parts.push(part),
+        }
+    }
+    if parts.is_empty() {
+        return Err(anyhow!("Path {path} is empty."));
+    }
+    Ok(parts.join("/"))
+}
+
+/// Every file below `dir`, relative to `root`.
+fn list_dir(root: &Path, dir: &Path, paths: &mut Vec<String>) {
+    let entries = match std::fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(_) => return,
+    };
+    for entry in entries.flatten() {
+        let path = entry.path();
+        if path.is_dir() {
+            list_dir(root, &path, paths);
+        } else if let Some(relative) = path.strip_prefix(root).ok().and_then(|path| path.to_str()) {
+            if let Ok(relative) = normalise(relative) {
+                paths.push(relative);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use crate::*;
+    use crate::pak::tests::init;
+    use std::fs::{create_dir_all, write};
+
+    #[test]
+    fn normalise_paths() {
+        assert!(normalise("/textures\\./WALL.png").unwrap() == "textures/WALL.png");
+        assert!(normalise("../secrets").is_err());
+        assert!(normalise("/").is_err());
+    }
+
+    #[test]
+    fn priority() {
+        let dir = tempfile::tempdir().unwrap();
+        let pak = init(dir.path());
+        let mod_dir = dir.path().join("mod");
+        create_dir_all(mod_dir.join("textures")).unwrap();
+        write(mod_dir.join("textures/WALL.png"), "modded wall").unwrap();
+        write(mod_dir.join("textures/NEW.png"), "new").unwrap();
+
+        let mut vfs = Vfs::new();
+        vfs.mount_pak(&pak).unwrap();
+        assert!(vfs.read("textures/WALL.png").unwrap() == b"wall");
+
+        vfs.mount_dir(&mod_dir).unwrap();
+        assert!(vfs.read("textures/WALL.png").unwrap() == b"modded wall");
+        assert!(vfs.read_to_string("maps/E1M1.json").unwrap().starts_with("[]"));
+        assert!(vfs.exists("textures/NEW.png") && !vfs.exists("textures/OLD.png"));
+        assert!(vfs.read("textures/OLD.png").is_err());
+        assert!(vfs.list("textures/") == vec!["textures/NEW.png", "textures/WALL.png"]);
+        assert!(vfs.list("/./textures\\") == vfs.list("textures/"));
+        assert!(vfs.list("/").len() == 3);
+        assert!(vfs.list("../textures/").is_empty());
+    }
+}
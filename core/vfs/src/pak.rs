@@ -0,0 +1,343 @@
+//! The `.pak` archive format. An archive is a header, the
+//! data of every file one after another, then an index of
+//! where each file is. All numbers are little endian.
+//!
+//! ```text
+//! header:  "RPAK"  version: u32  file count: u32  index offset: u64
+//! index:   for each file:
+//!          path length: u16  path: utf8  offset: u64
+//!          stored size: u64  size: u64  compression: u8
+//! ```
+
+use anyhow::{anyhow, Result};
+use flate2::read::DeflateDecoder;
+use flate2::write::DeflateEncoder;
+use std::collections::BTreeMap;
+use std::fs::{read_dir, File};
+use std::io::{BufReader, BufWriter, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use crate::normalise;
+
+/// The first four bytes of every archive.
+pub const PAK_MAGIC: &[u8; 4] = b"RPAK";
+
+/// The version of the format that is written.
+pub const PAK_VERSION: u32 = 1;
+
+const HEADER_SIZE: u64 = 20;
+
+/// The most that deflate can expand data by, used to catch sizes
+/// in a damaged index before allocating for them.
+const MAX_DEFLATE_RATIO: u64 = 1032;
+
+/// How a file is stored in an archive.
+#[derive(Clone, Copy, Default, Debug, PartialEq, Eq)]
+pub enum Compression {
+    #[default]
+    None,
+    Deflate,
+}
+impl Compression {
+    fn to_byte(self) -> u8 {
+        match self {
+            Compression::None => 0,
+            Compression::Deflate => 1,
+        }
+    }
+
+    fn from_byte(byte: u8) -> Result<Self> {
+        match byte {
+            0 => Ok(Compression::None),
+            1 => Ok(Compression::Deflate),
+            _ => Err(anyhow!("Unknown compression {byte} in archive.")),
+        }
+    }
+}
+
+/// Where a file is within an archive.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PakEntry {
+    pub offset: u64,
+    /// The number of bytes in the archive.
+    pub stored_size: u64,
+    /// The number of bytes once decompressed.
+    pub size: u64,
+    pub compression: Compression,
+}
+
+/// Builds an archive in memory and writes it out.
+#[derive(Clone, Default, Debug)]
+pub struct PakWriter {
+    files: BTreeMap<String, (Vec<u8>, Compression)>,
+}
+impl PakWriter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a file from memory, replacing any file at the same path.
+    pub fn add_bytes(&mut self, path: &str, bytes: Vec<u8>, compression: Compression) -> Result<()> {
+        self.files.insert(normalise(path)?, (bytes, compression));
+        Ok(())
+    }
+
+    /// Add a file from disk under a path within the archive.
+    pub fn add_file<P: AsRef<Path>>(&mut self, path: &str, source: P, compression: Compression) -> Result<()> {
+        self.add_bytes(path, std::fs::read(source)?, compression)
+    }
+
+    /// Add every file below a directory, keeping their paths
+    /// relative to it. Returns the number of files added.
+    pub fn add_dir<P: AsRef<Path>>(&mut self, dir: P, compression: Compression) -> Result<usize> {
+        let dir = dir.as_ref();
+        let mut files = vec![];
+        collect_files(dir, &mut files)?;
+        for file in &files {
+            let relative = file.strip_prefix(dir)?;
+            let path = relative
+                .to_str()
+                .ok_or_else(|| anyhow!("Path {} is not valid UTF-8.", relative.display()))?;
+            self.add_file(path, file, compression)?;
+        }
+        Ok(files.len())
+    }
+
+    /// The number of files that will be written.
+    pub fn len(&self) -> usize {
+        self.files.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.files.is_empty()
+    }
+
+    /// Write the archive. Files are written in path order, so the
+    /// same files always give the same archive.
+    pub fn write<W: Write>(&self, mut writer: W) -> Result<()> {
+        let mut data = vec![];
+        let mut index = vec![];
+        for (path, (bytes, compression)) in &self.files {
+            let stored = match compression {
+                Compression::None => bytes.clone(),
+                Compression::Deflate => {
+                    let mut encoder = DeflateEncoder::new(vec![], flate2::Compression::default());
+                    encoder.write_all(bytes)?;
+                    encoder.finish()?
+                }
+            };
+            let path_len: u16 = path
+                .len()
+                .try_into()
+                .map_err(|_| anyhow!("Path {path} is too long for an archive."))?;
+            index.extend(path_len.to_le_bytes());
+            index.extend(path.as_bytes());
+            index.extend((HEADER_SIZE + data.len() as u64).to_le_bytes());
+            index.extend((stored.len() as u64).to_le_bytes());
+            index.extend((bytes.len() as u64).to_le_bytes());
+            index.push(compression.to_byte());
+            data.extend(stored);
+        }
+
+        writer.write_all(PAK_MAGIC)?;
+        writer.write_all(&PAK_VERSION.to_le_bytes())?;
+        writer.write_all(&(self.files.len() as u32).to_le_bytes())?;
+        writer.write_all(&(HEADER_SIZE + data.len() as u64).to_le_bytes())?;
+        writer.write_all(&data)?;
+        writer.write_all(&index)?;
+        writer.flush()?;
+        Ok(())
+    }
+
+    /// Write the archive to a file.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        self.write(BufWriter::new(File::create(path)?))
+    }
+}
+
+/// An archive on disk. Only the index is kept in memory, and
+/// files are read from disk when they are asked for.
+#[derive(Clone, Debug)]
+pub struct PakReader {
+    path: PathBuf,
+    entries: BTreeMap<String, PakEntry>,
+}
+impl PakReader {
+    /// Open an archive and read its index.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        let mut file = BufReader::new(File::open(path)?);
+
+        let mut header = [0; HEADER_SIZE as usize];
+        file.read_exact(&mut header)
+            .map_err(|_| anyhow!("{} is too short to be an archive.", path.display()))?;
+        if &header[0..4] != PAK_MAGIC {
+            return Err(anyhow!("{} is not an archive.", path.display()));
+        }
+        let version = u32::from_le_bytes(header[4..8].try_into()?);
+        if version > PAK_VERSION {
+            return Err(anyhow!("{} is archive version {version}, newer than {PAK_VERSION}.", path.display()));
+        }
+        let count = u32::from_le_bytes(header[8..12].try_into()?);
+        let index_offset = u64::from_le_bytes(header[12..20].try_into()?);
+
+        file.seek(SeekFrom::Start(index_offset))?;
+        let mut entries = BTreeMap::new();
+        for _ in 0..count {
+            let mut path_len = [0; 2];
+            file.read_exact(&mut path_len)?;
+            let mut name = vec![0; u16::from_le_bytes(path_len) as usize];
+            file.read_exact(&mut name)?;
+            let mut fields = [0; 25];
+            file.read_exact(&mut fields)?;
+            entries.insert(
+                String::from_utf8(name)?,
+                PakEntry {
+                    offset: u64::from_le_bytes(fields[0..8].try_into()?),
+                    stored_size: u64::from_le_bytes(fields[8..16].try_into()?),
+                    size: u64::from_le_bytes(fields[16..24].try_into()?),
+                    compression: Compression::from_byte(fields[24])?,
+                },
+            );
+        }
+
+        Ok(PakReader {
+            path: path.to_path_buf(),
+            entries,
+        })
+    }
+
+    /// The path of the archive on disk.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Get the index entry of a file.
+    pub fn entry(&self, path: &str) -> Option<&PakEntry> {
+        self.entries.get(&normalise(path).ok()?)
+    }
+
+    /// Returns whether the archive has a file at a path.
+    pub fn contains(&self, path: &str) -> bool {
+        self.entry(path).is_some()
+    }
+
+    /// Every file path in the archive, in order.
+    pub fn paths(&self) -> impl Iterator<Item = &str> {
+        self.entries.keys().map(|path| path.as_str())
+    }
+
+    /// Read and decompress a file. The sizes in the index are
+    /// checked against the archive first, so a damaged index is an
+    /// error rather than a huge allocation.
+    pub fn read(&self, path: &str) -> Result<Vec<u8>> {
+        let entry = self
+            .entry(path)
+            .ok_or_else(|| anyhow!("No file {path} in {}.", self.path.display()))?;
+        let mut file = File::open(&self.path)?;
+        let archive_len = file.metadata()?.len();
+        let max_size = match entry.compression {
+            Compression::None => entry.stored_size,
+            Compression::Deflate => entry.stored_size.saturating_mul(MAX_DEFLATE_RATIO),
+        };
+        if entry.offset.saturating_add(entry.stored_size) > archive_len || entry.size > max_size {
+            return Err(anyhow!("File {path} in {} has a damaged index entry.", self.path.display()));
+        }
+        file.seek(SeekFrom::Start(entry.offset))?;
+        let stored = file.take(entry.stored_size);
+
+        // Read one byte past the size so that too much data is caught
+        let mut bytes = Vec::with_capacity(entry.size as usize);
+        match entry.compression {
+            Compression::None => BufReader::new(stored).read_to_end(&mut bytes)?,
+            Compression::Deflate => DeflateDecoder::new(stored).take(entry.size + 1).read_to_end(&mut bytes)?,
+        };
+        if bytes.len() as u64 != entry.size {
+            return Err(anyhow!("File {path} in {} is truncated.", self.path.display()));
+        }
+        Ok(bytes)
+    }
+}
+
+/// Every file below a directory, sorted.
+fn collect_files(dir: &Path, files: &mut Vec<PathBuf>) -> Result<()> {
+    let mut paths: Vec<PathBuf> = read_dir(dir)?
+        .map(|entry| entry.map(|entry| entry.path()))
+        .collect::<Result<_, _>>()?;
+    paths.sort();
+    for path in paths {
+        if path.is_dir() {
+            collect_files(&path, files)?;
+        } else {
+            files.push(path);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+pub mod tests {
+    use crate::pak::*;
+
+    /// Write an archive with one stored and one compressed file.
+    pub fn init(dir: &Path) -> PathBuf {
+        let mut writer = PakWriter::new();
+        writer
+            .add_bytes("textures/WALL.png", b"wall".to_vec(), Compression::None)
+            .unwrap();
+        writer
+            .add_bytes("maps/E1M1.json", b"[]".repeat(100), Compression::Deflate)
+            .unwrap();
+        let path = dir.join("base.pak");
+        writer.save(&path).unwrap();
+        path
+    }
+
+    #[test]
+    fn round_trip() {
+        let dir = tempfile::tempdir().unwrap();
+        let pak = PakReader::open(init(dir.path())).unwrap();
+        assert!(pak.paths().collect::<Vec<_>>() == vec!["maps/E1M1.json", "textures/WALL.png"]);
+        assert!(pak.read("textures/WALL.png").unwrap() == b"wall");
+        assert!(pak.read("/maps\\E1M1.json").unwrap() == b"[]".repeat(100));
+        assert!(pak.read("sounds/nothing.wav").is_err());
+
+        let map = pak.entry("maps/E1M1.json").unwrap();
+        assert!(map.compression == Compression::Deflate && map.stored_size < map.size);
+    }
+
+    #[test]
+    fn add_dir() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir_all(dir.path().join("assets/sprites")).unwrap();
+        std::fs::write(dir.path().join("assets/logo.png"), "logo").unwrap();
+        std::fs::write(dir.path().join("assets/sprites/imp.png"), "imp").unwrap();
+
+        let mut writer = PakWriter::new();
+        assert!(writer.add_dir(dir.path().join("assets"), Compression::Deflate).unwrap() == 2);
+        let path = dir.path().join("assets.pak");
+        writer.save(&path).unwrap();
+        assert!(PakReader::open(&path).unwrap().read("sprites/imp.png").unwrap() == b"imp");
+    }
+
+    #[test]
+    fn damaged_index() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut pak = PakReader::open(init(dir.path())).unwrap();
+        let wall = pak.entries.get_mut("textures/WALL.png").unwrap();
+        wall.stored_size = u64::MAX;
+        wall.size = u64::MAX;
+        assert!(pak.read("textures/WALL.png").is_err());
+
+        let map = pak.entries.get_mut("maps/E1M1.json").unwrap();
+        map.size = map.stored_size * MAX_DEFLATE_RATIO + 1;
+        assert!(pak.read("maps/E1M1.json").is_err());
+    }
+
+    #[test]
+    fn not_an_archive() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("fake.pak");
+        std::fs::write(&path, "PACKxxxxxxxxxxxxxxxxxxxx").unwrap();
+        assert!(PakReader::open(&path).is_err());
+    }
+}
@@ -0,0 +1,135 @@
+//! The camera of the 2D map view. Everything in the document is
+//! in world units, and the camera turns those into pixels on the
+//! canvas and back, so tools work the same at any pan and zoom.
+
+use notan::math::Vec2;
+
+/// The closest and furthest the camera can zoom, in pixels per
+/// world unit.
+pub const MIN_ZOOM: f32 = 1.0 / 64.0;
+pub const MAX_ZOOM: f32 = 64.0;
+
+/// A 2D camera made of the world position at the top left of the
+/// canvas and a zoom.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Camera {
+    pub origin: Vec2,
+    /// Pixels per world unit.
+    pub zoom: f32,
+}
+impl Default for Camera {
+    fn default() -> Self {
+        Camera {
+            origin: Vec2::ZERO,
+            zoom: 1.0,
+        }
+    }
+}
+impl Camera {
+    /// Turn a world position into a position on the canvas.
+    pub fn world_to_screen(&self, point: Vec2) -> Vec2 {
+        (point - self.origin) * self.zoom
+    }
+
+    /// Turn a position on the canvas into a world position.
+    pub fn screen_to_world(&self, point: Vec2) -> Vec2 {
+        point / self.zoom + self.origin
+    }
+
+    /// Turn a distance in pixels into a distance in world units.
+    pub fn screen_to_world_distance(&self, distance: f32) -> f32 {
+        distance / self.zoom
+    }
+
+    /// Move the view by a distance in pixels, so that the world
+    /// follows the mouse when dragging.
+    pub fn pan(&mut self, screen_delta: Vec2) {
+        self.origin -= screen_delta / self.zoom;
+    }
+
+    /// Zoom by a factor, keeping the world position under `anchor`
+    /// on the canvas where it is.
+    pub fn zoom_at(&mut self, anchor: Vec2, factor: f32) {
+        let world = self.screen_to_world(anchor);
+        self.zoom = (self.zoom * factor).clamp(MIN_ZOOM, MAX_ZOOM);
+        self.origin = world - anchor / self.zoom;
+    }
+
+    /// Pan and zoom so that the world rectangle from `min` to `max`
+    /// fills a canvas of `size` pixels, leaving `margin` pixels
+    /// around it.
+    pub fn zoom_to_fit(&mut self, min: Vec2, max: Vec2, size: Vec2, margin: f32) {
+        let extent = (max - min).max(Vec2::splat(1.0));
+        let available = (size - Vec2::splat(margin * 2.0)).max(Vec2::splat(1.0));
+        self.zoom = (available / extent).min_element().clamp(MIN_ZOOM, MAX_ZOOM);
+        // Centre the rectangle on the canvas
+        let centre = (min + max) / 2.0;
+        self.origin = centre - size / 2.0 / self.zoom;
+    }
+
+    /// The world rectangle that the canvas shows.
+    pub fn visible(&self, size: Vec2) -> (Vec2, Vec2) {
+        (self.origin, self.screen_to_world(size))
+    }
+}
+
+/// The smallest rectangle holding every point, or `None` if there
+/// are no points.
+pub fn bounds<I: IntoIterator<Item = Vec2>>(points: I) -> Option<(Vec2, Vec2)> {
+    points.into_iter().fold(None, |bounds, point| match bounds {
+        None => Some((point, point)),
+        Some((min, max)) => Some((min.min(point), max.max(point))),
+    })
+}
+
+#[cfg(test)]
+pub mod tests {
+    use crate::camera::*;
+
+    fn close(a: Vec2, b: Vec2) -> bool {
+        a.distance(b) < 1e-3
+    }
+
+    #[test]
+    fn round_trip() {
+        let camera = Camera {
+            origin: Vec2::new(-100.0, 50.0),
+            zoom: 2.5,
+        };
+        let point = Vec2::new(12.0, -7.0);
+        assert!(close(camera.screen_to_world(camera.world_to_screen(point)), point));
+        assert!(camera.world_to_screen(Vec2::new(-100.0, 50.0)) == Vec2::ZERO);
+    }
+
+    #[test]
+    fn pan() {
+        let mut camera = Camera { origin: Vec2::ZERO, zoom: 2.0 };
+        let world = Vec2::new(10.0, 10.0);
+        let before = camera.world_to_screen(world);
+        camera.pan(Vec2::new(30.0, -20.0));
+        assert!(close(camera.world_to_screen(world), before + Vec2::new(30.0, -20.0)));
+    }
+
+    #[test]
+    fn zoom_keeps_anchor() {
+        let mut camera = Camera::default();
+        let anchor = Vec2::new(300.0, 200.0);
+        let world = camera.screen_to_world(anchor);
+        camera.zoom_at(anchor, 4.0);
+        assert!(camera.zoom == 4.0);
+        assert!(close(camera.screen_to_world(anchor), world));
+
+        camera.zoom_at(anchor, 1000.0);
+        assert!(camera.zoom == MAX_ZOOM);
+    }
+
+    #[test]
+    fn fit() {
+        let mut camera = Camera::default();
+        let (min, max) = bounds([Vec2::new(0.0, 0.0), Vec2::new(400.0, 100.0), Vec2::new(200.0, -100.0)]).unwrap();
+        camera.zoom_to_fit(min, max, Vec2::new(800.0, 600.0), 0.0);
+        assert!(camera.zoom == 2.0);
+        assert!(close(camera.world_to_screen(Vec2::new(200.0, 0.0)), Vec2::new(400.0, 300.0)));
+        assert!(bounds(vec![]).is_none());
+    }
+}
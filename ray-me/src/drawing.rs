@@ -0,0 +1,84 @@
+//! Drawing chains of lines on the canvas with the mouse. Each
+//! left click places a vertex, right click keeps the chain and
+//! Escape throws it away. Nothing is added to the document until
+//! the chain is finished.
+
+use notan::math::Vec2;
+
+/// The vertices placed so far in the chain being drawn.
+#[derive(Clone, Default, Debug, PartialEq)]
+pub struct LineTool {
+    points: Vec<Vec2>,
+}
+impl LineTool {
+    /// Returns whether a chain has been started.
+    pub fn is_drawing(&self) -> bool {
+        !self.points.is_empty()
+    }
+
+    /// The last vertex placed, which the next line starts from.
+    pub fn last(&self) -> Option<Vec2> {
+        self.points.last().copied()
+    }
+
+    /// Place a vertex. Clicking twice on the same spot does not
+    /// make a zero length line.
+    pub fn click(&mut self, point: Vec2) {
+        if self.last() != Some(point) {
+            self.points.push(point);
+        }
+    }
+
+    /// The lines of the chain placed so far.
+    pub fn lines(&self) -> Vec<(Vec2, Vec2)> {
+        self.points.windows(2).map(|pair| (pair[0], pair[1])).collect()
+    }
+
+    /// The rubber band line from the last vertex to the cursor.
+    pub fn preview(&self, cursor: Vec2) -> Option<(Vec2, Vec2)> {
+        self.last().map(|last| (last, cursor))
+    }
+
+    /// End the chain, giving back its lines to add to the map.
+    pub fn finish(&mut self) -> Vec<(Vec2, Vec2)> {
+        let lines = self.lines();
+        self.points.clear();
+        lines
+    }
+
+    /// Throw the chain away.
+    pub fn cancel(&mut self) {
+        self.points.clear();
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use crate::drawing::*;
+
+    #[test]
+    fn chain() {
+        let mut tool = LineTool::default();
+        assert!(tool.preview(Vec2::ZERO).is_none());
+
+        tool.click(Vec2::new(0.0, 0.0));
+        tool.click(Vec2::new(0.0, 0.0));
+        tool.click(Vec2::new(10.0, 0.0));
+        tool.click(Vec2::new(10.0, 10.0));
+        assert!(tool.preview(Vec2::new(5.0, 5.0)) == Some((Vec2::new(10.0, 10.0), Vec2::new(5.0, 5.0))));
+
+        let lines = tool.finish();
+        assert!(lines.len() == 2);
+        assert!(lines[1] == (Vec2::new(10.0, 0.0), Vec2::new(10.0, 10.0)));
+        assert!(!tool.is_drawing());
+    }
+
+    #[test]
+    fn cancel() {
+        let mut tool = LineTool::default();
+        tool.click(Vec2::new(0.0, 0.0));
+        tool.click(Vec2::new(10.0, 0.0));
+        tool.cancel();
+        assert!(!tool.is_drawing() && tool.finish().is_empty());
+    }
+}
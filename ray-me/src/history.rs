@@ -0,0 +1,550 @@
+//! Undo and redo. Every change to the map is a `Command` that
+//! knows how to apply and revert itself, and the `History` keeps
+//! the commands that have been done and undone. It also knows
+//! whether the document has changed since it was last saved.
+
+use core::level_geometry::document::{EditorSettings, MapDocument, MapMetadata, Sector, Thing};
+use core::level_geometry::geometry::Seg;
+use std::collections::{BTreeMap, VecDeque};
+
+/// The number of commands kept by default.
+pub const DEFAULT_DEPTH: usize = 200;
+
+/// A reversible change to a document. Changes and deletions keep
+/// the indices of what they touched, in ascending order.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Command {
+    /// Insert segs starting at an index.
+    AddSegs { at: usize, segs: Vec<Seg> },
+    DeleteSegs(Vec<(usize, Seg)>),
+    /// Change segs in place, such as moving them.
+    ChangeSegs { before: Vec<(usize, Seg)>, after: Vec<(usize, Seg)> },
+    DeleteThings(Vec<(usize, Thing)>),
+    ChangeThings { before: Vec<(usize, Thing)>, after: Vec<(usize, Thing)> },
+    /// Add a sector to the end of the sectors.
+    AddSector(Sector),
+    ChangeSectors { before: Vec<(usize, Sector)>, after: Vec<(usize, Sector)> },
+    ChangeMetadata { before: MapMetadata, after: MapMetadata },
+    /// Change the grid and snapping settings saved with the map.
+    ChangeEditor { before: EditorSettings, after: EditorSettings },
+    /// Several commands done and undone as one.
+    Group(Vec<Command>),
+}
+impl Command {
+    /// The command for changing `before` into `after`, which must be
+    /// the same length. `None` if nothing changed.
+    pub fn change_segs(before: &[Seg], after: &[Seg]) -> Option<Command> {
+        changes(before, after).map(|(before, after)| Command::ChangeSegs { before, after })
+    }
+
+    /// Like `change_segs`, for things.
+    pub fn change_things(before: &[Thing], after: &[Thing]) -> Option<Command> {
+        changes(before, after).map(|(before, after)| Command::ChangeThings { before, after })
+    }
+
+    /// Like `change_segs`, for sectors.
+    pub fn change_sectors(before: &[Sector], after: &[Sector]) -> Option<Command> {
+        changes(before, after).map(|(before, after)| Command::ChangeSectors { before, after })
+    }
+
+    /// Put commands together into one. `None` if there are none.
+    pub fn group(mut commands: Vec<Command>) -> Option<Command> {
+        match commands.len() {
+            0 => None,
+            1 => commands.pop(),
+            _ => Some(Command::Group(commands)),
+        }
+    }
+
+    /// Make the change.
+    pub fn apply(&self, document: &mut MapDocument) {
+        let segs = &mut document.geometry.segs;
+        match self {
+            Command::AddSegs { at, segs: added } => {
+                segs.splice(*at..*at, added.iter().copied());
+            }
+            Command::DeleteSegs(removed) => remove_all(segs, removed),
+            Command::ChangeSegs { after, .. } => set_all(segs, after),
+            Command::DeleteThings(removed) => remove_all(&mut document.things, removed),
+            Command::ChangeThings { after, .. } => set_all(&mut document.things, after),
+            Command::AddSector(sector) => document.geometry.sectors.push(sector.clone()),
+            Command::ChangeSectors { after, .. } => set_all(&mut document.geometry.sectors, after),
+            Command::ChangeMetadata { after, .. } => document.metadata = after.clone(),
+            Command::ChangeEditor { after, .. } => document.editor = after.clone(),
+            Command::Group(commands) => {
+                for command in commands {
+                    command.apply(document);
+                }
+            }
+        }
+    }
+
+    /// Undo the change.
+    pub fn revert(&self, document: &mut MapDocument) {
+        let segs = &mut document.geometry.segs;
+        match self {
+            Command::AddSegs { at, segs: added } => {
+                segs.drain(*at..*at + added.len());
+            }
+            Command::DeleteSegs(removed) => insert_all(segs, removed),
+            Command::ChangeSegs { before, .. } => set_all(segs, before),
+            Command::DeleteThings(removed) => insert_all(&mut document.things, removed),
+            Command::ChangeThings { before, .. } => set_all(&mut document.things, before),
+            Command::AddSector(_) => {
+                document.geometry.sectors.pop();
+            }
+            Command::ChangeSectors { before, .. } => set_all(&mut document.geometry.sectors, before),
+            Command::ChangeMetadata { before, .. } => document.metadata = before.clone(),
+            Command::ChangeEditor { before, .. } => document.editor = before.clone(),
+            Command::Group(commands) => {
+                for command in commands.iter().rev() {
+                    command.revert(document);
+                }
+            }
+        }
+    }
+
+    /// Fold a command that came straight after this one into it, so
+    /// that both are undone together. Returns whether they merged.
+    fn merge(&mut self, next: &Command) -> bool {
+        match (self, next) {
+            (
+                Command::ChangeSegs { before, after },
+                Command::ChangeSegs { before: next_before, after: next_after },
+            ) => merge_changes(before, after, next_before, next_after),
+            (
+                Command::ChangeThings { before, after },
+                Command::ChangeThings { before: next_before, after: next_after },
+            ) => merge_changes(before, after, next_before, next_after),
+            (
+                Command::ChangeSectors { before, after },
+                Command::ChangeSectors { before: next_before, after: next_after },
+            ) => merge_changes(before, after, next_before, next_after),
+            (Command::ChangeMetadata { after, .. }, Command::ChangeMetadata { after: next_after, .. }) => {
+                *after = next_after.clone();
+                true
+            }
+            (Command::Group(commands), Command::Group(next)) if commands.len() == next.len() => {
+                // Only merge if every part does
+                let mut merged = commands.clone();
+                let all = merged.iter_mut().zip(next).all(|(command, next)| command.merge(next));
+                if all {
+                    *commands = merged;
+                }
+                all
+            }
+            _ => false,
+        }
+    }
+}
+
+/// What is different between two lists of the same length, as
+/// the before and after of each item that changed.
+type Changes<T> = (Vec<(usize, T)>, Vec<(usize, T)>);
+fn changes<T: Clone + PartialEq>(before: &[T], after: &[T]) -> Option<Changes<T>> {
+    let (before, after): Changes<T> = before
+        .iter()
+        .zip(after)
+        .enumerate()
+        .filter(|(_, (before, after))| before != after)
+        .map(|(index, (before, after))| ((index, before.clone()), (index, after.clone())))
+        .unzip();
+    match before.is_empty() {
+        true => None,
+        false => Some((before, after)),
+    }
+}
+
+fn set_all<T: Clone>(items: &mut [T], changes: &[(usize, T)]) {
+    for (index, item) in changes {
+        items[*index] = item.clone();
+    }
+}
+
+fn remove_all<T>(items: &mut Vec<T>, removed: &[(usize, T)]) {
+    for (index, _) in removed.iter().rev() {
+        items.remove(*index);
+    }
+}
+
+fn insert_all<T: Clone>(items: &mut Vec<T>, removed: &[(usize, T)]) {
+    for (index, item) in removed {
+        items.insert(*index, item.clone());
+    }
+}
+
+/// Keep the earliest before and the latest after of every item.
+fn merge_changes<T: Clone>(
+    before: &mut Vec<(usize, T)>,
+    after: &mut Vec<(usize, T)>,
+    next_before: &[(usize, T)],
+    next_after: &[(usize, T)],
+) -> bool {
+    let mut befores: BTreeMap<usize, T> = next_before.iter().cloned().collect();
+    befores.extend(before.iter().cloned());
+    let mut afters: BTreeMap<usize, T> = after.iter().cloned().collect();
+    afters.extend(next_after.iter().cloned());
+    *before = befores.into_iter().collect();
+    *after = afters.into_iter().collect();
+    true
+}
+
+/// A command that has been done, and whether later commands can
+/// still be merged into it. The id tells entries apart when
+/// checking whether the document is back where it was saved.
+#[derive(Clone, Debug)]
+struct Entry {
+    command: Command,
+    open: bool,
+    id: u64,
+}
+
+/// The commands that can be undone and redone, oldest first.
+#[derive(Clone, Debug)]
+pub struct History {
+    done: VecDeque<Entry>,
+    undone: Vec<Entry>,
+    depth: usize,
+    next_id: u64,
+    /// The id of the last entry done when the document was saved,
+    /// `None` if nothing had been done. `u64::MAX`, which no entry
+    /// has, once that entry has been forgotten and the saved
+    /// document can no longer be undone back to.
+    saved: Option<u64>,
+}
+impl Default for History {
+    fn default() -> Self {
+        Self::with_depth(DEFAULT_DEPTH)
+    }
+}
+impl History {
+    /// Create a history that keeps at most `depth` commands.
+    pub fn with_depth(depth: usize) -> Self {
+        History {
+            done: VecDeque::new(),
+            undone: vec![],
+            depth,
+            next_id: 0,
+            saved: None,
+        }
+    }
+
+    pub fn depth(&self) -> usize {
+        self.depth
+    }
+
+    /// Change how many commands are kept, forgetting the oldest
+    /// ones if there are too many.
+    pub fn set_depth(&mut self, depth: usize) {
+        self.depth = depth;
+        self.trim();
+    }
+
+    /// Apply a command and record it.
+    pub fn execute(&mut self, document: &mut MapDocument, command: Command) {
+        command.apply(document);
+        self.record(command);
+    }
+
+    /// Record a command that has already been applied.
+    pub fn record(&mut self, command: Command) {
+        self.seal();
+        self.push(command, false);
+    }
+
+    /// Apply a command and record it with `record_coalesced`.
+    pub fn execute_coalesced(&mut self, document: &mut MapDocument, command: Command) {
+        command.apply(document);
+        self.record_coalesced(command);
+    }
+
+    /// Record a command that has already been applied, merging it
+    /// into the last one if that was also coalesced and has not been
+    /// sealed since. Used for drags, which change the map every frame
+    /// but should be undone in one go.
+    pub fn record_coalesced(&mut self, command: Command) {
+        if let Some(last) = self.done.back_mut().filter(|last| last.open) {
+            if last.command.merge(&command) {
+                self.undone.clear();
+                return;
+            }
+        }
+        self.push(command, true);
+    }
+
+    /// Stop the last command from having anything more merged in.
+    pub fn seal(&mut self) {
+        if let Some(last) = self.done.back_mut() {
+            last.open = false;
+        }
+    }
+
+    pub fn can_undo(&self) -> bool {
+        !self.done.is_empty()
+    }
+
+    pub fn can_redo(&self) -> bool {
+        !self.undone.is_empty()
+    }
+
+    /// Revert the last command. Returns whether there was one.
+    pub fn undo(&mut self, document: &mut MapDocument) -> bool {
+        match self.done.pop_back() {
+            Some(entry) => {
+                entry.command.revert(document);
+                self.undone.push(entry);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Apply the last undone command again. Returns whether there
+    /// was one.
+    pub fn redo(&mut self, document: &mut MapDocument) -> bool {
+        match self.undone.pop() {
+            Some(entry) => {
+                entry.command.apply(document);
+                self.done.push_back(Entry { open: false, ..entry });
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Returns whether the document has changed since it was saved.
+    pub fn is_dirty(&self) -> bool {
+        self.done.back().map(|entry| entry.id) != self.saved
+    }
+
+    /// Remember that the document as it is now has been saved.
+    pub fn mark_saved(&mut self) {
+        // Nothing more can be merged into the saved command, or the
+        // document would change without it counting as a change
+        self.seal();
+        self.saved = self.done.back().map(|entry| entry.id);
+    }
+
+    /// Forget every command, such as when another map is opened,
+    /// and count the document as saved.
+    pub fn clear(&mut self) {
+        self.done.clear();
+        self.undone.clear();
+        self.saved = None;
+    }
+
+    fn push(&mut self, command: Command, open: bool) {
+        self.done.push_back(Entry { command, open, id: self.next_id });
+        self.next_id += 1;
+        self.undone.clear();
+        self.trim();
+    }
+
+    fn trim(&mut self) {
+        while self.done.len() > self.depth {
+            if let Some(entry) = self.done.pop_front() {
+                if self.saved.is_none() || self.saved == Some(entry.id) {
+                    self.saved = Some(u64::MAX);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use crate::history::*;
+    use core::level_geometry::geometry::Line;
+    use notan::math::Vec3;
+
+    fn seg(x: f32) -> Seg {
+        Seg::new((Vec3::new(x, 0.0, 0.0), Vec3::new(x, 10.0, 0.0)), 100.0)
+    }
+
+    fn init() -> MapDocument {
+        MapDocument::from_segs(vec![seg(0.0), seg(1.0), seg(2.0)])
+    }
+
+    #[test]
+    fn add_and_delete() {
+        let mut document = init();
+        let mut history = History::default();
+
+        history.execute(&mut document, Command::AddSegs { at: 3, segs: vec![seg(3.0)] });
+        history.execute(&mut document, Command::DeleteSegs(vec![(0, seg(0.0)), (2, seg(2.0))]));
+        assert!(document.geometry.segs == vec![seg(1.0), seg(3.0)]);
+
+        assert!(history.undo(&mut document));
+        assert!(document.geometry.segs == vec![seg(0.0), seg(1.0), seg(2.0), seg(3.0)]);
+        assert!(history.undo(&mut document));
+        assert!(document == init());
+        assert!(!history.undo(&mut document));
+
+        assert!(history.redo(&mut document) && history.redo(&mut document));
+        assert!(document.geometry.segs == vec![seg(1.0), seg(3.0)]);
+        assert!(!history.redo(&mut document));
+    }
+
+    #[test]
+    fn new_command_clears_redo() {
+        let mut document = init();
+        let mut history = History::default();
+        history.execute(&mut document, Command::AddSegs { at: 0, segs: vec![seg(9.0)] });
+        history.undo(&mut document);
+        assert!(history.can_redo());
+        history.execute(&mut document, Command::DeleteSegs(vec![(0, seg(0.0))]));
+        assert!(!history.can_redo());
+    }
+
+    #[test]
+    fn coalesce_drag() {
+        let mut document = init();
+        let mut history = History::default();
+
+        // Three frames of dragging the first seg along
+        for x in [5.0, 6.0, 7.0] {
+            let before = document.geometry.segs.clone();
+            document.geometry.segs[0] = seg(x);
+            let command = Command::change_segs(&before, &document.geometry.segs).unwrap();
+            history.record_coalesced(command);
+        }
+        history.seal();
+        // A separate drag after the first one ended
+        let before = document.geometry.segs.clone();
+        document.geometry.segs[1] = seg(8.0);
+        history.record_coalesced(Command::change_segs(&before, &document.geometry.segs).unwrap());
+
+        history.undo(&mut document);
+        assert!(document.geometry.segs == vec![seg(7.0), seg(1.0), seg(2.0)]);
+        history.undo(&mut document);
+        assert!(document == init());
+        assert!(!history.can_undo());
+    }
+
+    #[test]
+    fn change_segs_without_change() {
+        let segs = init().geometry.segs;
+        assert!(Command::change_segs(&segs, &segs).is_none());
+    }
+
+    #[test]
+    fn sector_and_metadata() {
+        let mut document = init();
+        let mut history = History::default();
+        history.execute(&mut document, Command::AddSector(Sector::default()));
+        let bright = Sector { light_level: 200, ..Default::default() };
+        let command = Command::change_sectors(&document.geometry.sectors, std::slice::from_ref(&bright)).unwrap();
+        history.execute(&mut document, command);
+        let named = MapMetadata { name: "E1M1".to_owned(), ..Default::default() };
+        history.execute(
+            &mut document,
+            Command::ChangeMetadata { before: MapMetadata::default(), after: named },
+        );
+        assert!(document.geometry.sectors == vec![bright] && document.metadata.name == "E1M1");
+
+        while history.undo(&mut document) {}
+        assert!(document == init());
+    }
+
+    #[test]
+    fn editor_settings() {
+        let mut document = init();
+        let mut history = History::default();
+        let after = EditorSettings { grid_size: 64.0, show_grid: false, ..Default::default() };
+        let command = Command::ChangeEditor { before: document.editor.clone(), after: after.clone() };
+        history.execute(&mut document, command);
+        assert!(document.editor == after);
+        assert!(history.is_dirty());
+
+        history.undo(&mut document);
+        assert!(document == init());
+    }
+
+    #[test]
+    fn dirty() {
+        let mut document = init();
+        let mut history = History::default();
+        assert!(!history.is_dirty());
+
+        history.execute(&mut document, Command::AddSegs { at: 0, segs: vec![seg(9.0)] });
+        assert!(history.is_dirty());
+        history.mark_saved();
+        assert!(!history.is_dirty());
+
+        // Undoing past the save and redoing back to it
+        history.undo(&mut document);
+        assert!(history.is_dirty());
+        history.redo(&mut document);
+        assert!(!history.is_dirty());
+
+        // A drag after saving is not merged into the saved command
+        let before = document.geometry.segs.clone();
+        document.geometry.segs[0] = seg(5.0);
+        history.record_coalesced(Command::change_segs(&before, &document.geometry.segs).unwrap());
+        history.mark_saved();
+        let before = document.geometry.segs.clone();
+        document.geometry.segs[0] = seg(6.0);
+        history.record_coalesced(Command::change_segs(&before, &document.geometry.segs).unwrap());
+        assert!(history.is_dirty());
+
+        history.clear();
+        assert!(!history.is_dirty());
+    }
+
+    #[test]
+    fn dirty_after_trimming_the_save() {
+        let mut document = init();
+        let mut history = History::with_depth(1);
+        history.execute(&mut document, Command::AddSegs { at: 0, segs: vec![seg(7.0)] });
+        history.execute(&mut document, Command::AddSegs { at: 0, segs: vec![seg(6.0)] });
+        // Nothing is left to undo, but the first command still
+        // changed the document from how it was saved
+        history.undo(&mut document);
+        assert!(!history.can_undo() && history.is_dirty());
+    }
+
+    #[test]
+    fn group() {
+        let mut document = init();
+        document.things.push(Thing::default());
+        let before = document.clone();
+        let mut history = History::default();
+
+        // Moving a seg and a thing together, over two frames
+        for x in [5.0, 6.0] {
+            let moved = Thing { pos: Vec3::new(x, 0.0, 0.0), ..Default::default() };
+            let command = Command::group(vec![
+                Command::change_segs(&document.geometry.segs, &[seg(x), seg(1.0), seg(2.0)]).unwrap(),
+                Command::change_things(&document.things, &[moved]).unwrap(),
+            ]);
+            history.execute_coalesced(&mut document, command.unwrap());
+        }
+        assert!(document.things[0].pos.x == 6.0 && document.geometry.segs[0] == seg(6.0));
+
+        let delete = Command::group(vec![
+            Command::DeleteSegs(vec![(1, seg(1.0))]),
+            Command::DeleteThings(vec![(0, document.things[0])]),
+        ]);
+        history.execute(&mut document, delete.unwrap());
+        assert!(document.things.is_empty() && document.geometry.segs.len() == 2);
+
+        assert!(history.undo(&mut document) && history.undo(&mut document));
+        assert!(document == before);
+        assert!(Command::group(vec![]).is_none());
+    }
+
+    #[test]
+    fn depth() {
+        let mut document = init();
+        let mut history = History::with_depth(2);
+        for x in 0..3 {
+            history.execute(&mut document, Command::AddSegs { at: 0, segs: vec![seg(x as f32 + 10.0)] });
+        }
+        assert!(history.undo(&mut document) && history.undo(&mut document));
+        assert!(!history.undo(&mut document));
+        assert!(document.geometry.segs.len() == 4);
+
+        history.set_depth(0);
+        assert!(!history.can_undo());
+    }
+}
@@ -0,0 +1,160 @@
+//! What the property inspector shows and changes. A property that
+//! every selected item shares is shown as it is, and one that
+//! differs is shown as mixed. Editing either sets it on all of
+//! the selected items.
+
+use crate::history::Command;
+use crate::selection::Selection;
+use core::level_geometry::document::{Sector, Thing};
+use core::level_geometry::geometry::Seg;
+use std::collections::BTreeSet;
+
+/// A property across the selection.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Shared<T> {
+    /// Nothing is selected.
+    Empty,
+    Same(T),
+    /// The values differ, starting with this one.
+    Mixed(T),
+}
+impl<T: PartialEq> Shared<T> {
+    pub fn of<I: IntoIterator<Item = T>>(values: I) -> Self {
+        let mut values = values.into_iter();
+        let Some(first) = values.next() else {
+            return Shared::Empty;
+        };
+        match values.all(|value| value == first) {
+            true => Shared::Same(first),
+            false => Shared::Mixed(first),
+        }
+    }
+}
+impl<T> Shared<T> {
+    /// The value to show, which is the first one if they differ.
+    pub fn value(self) -> Option<T> {
+        match self {
+            Shared::Empty => None,
+            Shared::Same(value) | Shared::Mixed(value) => Some(value),
+        }
+    }
+
+    pub fn is_mixed(&self) -> bool {
+        matches!(self, Shared::Mixed(_))
+    }
+
+    pub fn map<U, F: FnOnce(T) -> U>(self, f: F) -> Shared<U> {
+        match self {
+            Shared::Empty => Shared::Empty,
+            Shared::Same(value) => Shared::Same(f(value)),
+            Shared::Mixed(value) => Shared::Mixed(f(value)),
+        }
+    }
+}
+
+/// The sectors that the selected segs face into. Sectors that do
+/// not exist are left out.
+pub fn selected_sectors(segs: &[Seg], sector_count: usize, selection: &Selection) -> BTreeSet<usize> {
+    selection
+        .segs
+        .iter()
+        .filter_map(|index| segs.get(*index)?.sector())
+        .filter(|sector| *sector < sector_count)
+        .collect()
+}
+
+/// The command for making an edit to each of the segs at
+/// `indices`. `None` if the edit changes nothing.
+pub fn edit_segs<F: Fn(&mut Seg)>(segs: &[Seg], indices: &BTreeSet<usize>, edit: F) -> Option<Command> {
+    Command::change_segs(segs, &edited(segs, indices, edit))
+}
+
+/// Like `edit_segs`, for things.
+pub fn edit_things<F: Fn(&mut Thing)>(things: &[Thing], indices: &BTreeSet<usize>, edit: F) -> Option<Command> {
+    Command::change_things(things, &edited(things, indices, edit))
+}
+
+/// Like `edit_segs`, for sectors.
+pub fn edit_sectors<F: Fn(&mut Sector)>(sectors: &[Sector], indices: &BTreeSet<usize>, edit: F) -> Option<Command> {
+    Command::change_sectors(sectors, &edited(sectors, indices, edit))
+}
+
+fn edited<T: Clone, F: Fn(&mut T)>(items: &[T], indices: &BTreeSet<usize>, edit: F) -> Vec<T> {
+    let mut items = items.to_vec();
+    for index in indices {
+        if let Some(item) = items.get_mut(*index) {
+            edit(item);
+        }
+    }
+    items
+}
+
+#[cfg(test)]
+pub mod tests {
+    use crate::inspector::*;
+    use crate::selection::Hit;
+    use core::level_geometry::document::MapDocument;
+    use core::level_geometry::geometry::{Line, TextureName};
+    use notan::math::Vec3;
+
+    fn init() -> MapDocument {
+        let seg = |x: f32, height: f32| Seg::new((Vec3::new(x, 0.0, 0.0), Vec3::new(x, 10.0, 0.0)), height);
+        let mut document = MapDocument::from_segs(vec![
+            seg(0.0, 100.0).with_sector(Some(0)),
+            seg(1.0, 100.0).with_sector(Some(1)),
+            seg(2.0, 50.0).with_sector(Some(7)),
+        ]);
+        document.geometry.sectors = vec![Sector::default(), Sector::default()];
+        document
+    }
+
+    #[test]
+    fn shared() {
+        assert!(Shared::of([1, 1, 1]) == Shared::Same(1));
+        assert!(Shared::of([2, 1, 2]) == Shared::Mixed(2));
+        assert!(Shared::of(Vec::<u32>::new()) == Shared::Empty);
+        assert!(Shared::of([2, 1]).value() == Some(2) && Shared::of([2, 1]).is_mixed());
+    }
+
+    #[test]
+    fn sectors_of_selection() {
+        let document = init();
+        let mut selection = Selection::default();
+        for index in 0..3 {
+            selection.insert(Hit::Seg(index));
+        }
+        let sectors = selected_sectors(&document.geometry.segs, document.geometry.sectors.len(), &selection);
+        assert!(sectors == BTreeSet::from([0, 1]));
+    }
+
+    #[test]
+    fn edit_mixed_segs() {
+        let mut document = init();
+        let indices = BTreeSet::from([0, 2]);
+        let segs = &document.geometry.segs;
+        assert!(Shared::of(indices.iter().map(|index| segs[*index].height())).is_mixed());
+
+        let texture = TextureName::new("STARTAN3").unwrap();
+        let command = edit_segs(segs, &indices, |seg| *seg = seg.with_height(80.0).with_texture(texture));
+        command.unwrap().apply(&mut document);
+        let segs = &document.geometry.segs;
+        assert!(Shared::of(indices.iter().map(|index| segs[*index].height())) == Shared::Same(80.0));
+        assert!(segs[0].texture() == texture && segs[1].texture().is_empty());
+
+        // Setting a value they already have is not a change
+        assert!(edit_segs(segs, &indices, |seg| *seg = seg.with_height(80.0)).is_none());
+    }
+
+    #[test]
+    fn edit_sectors_and_things() {
+        let mut document = init();
+        document.things.push(Thing::default());
+        let command = edit_sectors(&document.geometry.sectors, &BTreeSet::from([1]), |sector| sector.light_level = 64);
+        command.unwrap().apply(&mut document);
+        let command = edit_things(&document.things, &BTreeSet::from([0, 5]), |thing| thing.angle_deg = 90.0);
+        command.unwrap().apply(&mut document);
+
+        assert!(document.geometry.sectors[1].light_level == 64 && document.geometry.sectors[0].light_level == 255);
+        assert!(document.things[0].angle_deg == 90.0);
+    }
+}
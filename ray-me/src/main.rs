@@ -1,40 +1,135 @@
-use notan::draw::{CreateDraw, DrawShapes, DrawConfig};
+use notan::draw::{CreateDraw, Draw, DrawShapes, DrawConfig};
 use notan::egui::{self, *};
 use notan::math::Vec3;
 use notan::math::Vec3Swizzles;
 use notan::prelude::*;
-use core::level_geometry::geometry::{Seg, Line};
+use core::level_geometry::document::{EditorSettings, MapDocument, Thing};
+use core::level_geometry::geometry::{Seg, Line, TextureName};
+use core::level_geometry::document::Sector;
+use std::collections::BTreeSet;
 use serde_json;
-use std::fs::{write, read};
-use std::io::BufReader;
-use core::level_geometry::partitioning::non_recursive_partition;
+use std::fs::write;
+use std::path::{Path, PathBuf};
+use anyhow::Context as _;
+use core::level_geometry::partitioning::compile_document;
+use core::textures::manifest::TextureManifest;
+use notan::math::Vec2;
+
+mod camera;
+mod drawing;
+mod history;
+mod inspector;
+mod notifications;
+mod selection;
+mod snapping;
+
+use camera::Camera;
+use drawing::LineTool;
+use history::{Command, History};
+use inspector::Shared;
+use notifications::{Level, Notifications};
+use selection::{Moving, Selection};
+
+const TITLE: &str = "RayME - The Rayst Map Editor";
+
+/// How close in pixels the cursor has to be to a vertex to snap to it.
+const SNAP_RADIUS: f32 = 8.0;
+
+/// The size in pixels things are drawn at.
+const THING_RADIUS: f32 = 6.0;
+
+/// How much one notch of the mouse wheel zooms by.
+const ZOOM_STEP: f32 = 1.25;
+
+/// Space in pixels left around the map when zooming to fit.
+const FIT_MARGIN: f32 = 32.0;
+
+/// The tool that clicks on the canvas go to.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum Tool {
+    Select,
+    DrawLines,
+}
+
+/// Something that throws away the open document, and so has to
+/// ask first if there are unsaved changes. Closing the window
+/// cannot be stopped, so only quitting from the File menu asks.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum Discard {
+    New,
+    Open,
+    Quit,
+}
+
+/// What dragging with the select tool is doing.
+#[derive(Clone, Debug)]
+enum Drag {
+    /// Moving the selection. `applied` is how far it has been
+    /// moved so far, so that moves can snap to the grid.
+    Move { origin: Vec2, applied: Vec2, moving: Moving },
+    /// Dragging out a selection box from `start`.
+    Box { start: Vec2 },
+}
 
 #[derive(AppState)]
 struct State {
     tools_open: bool,
-    l1_x: String,
-    l1_y: String,
-    l2_x: String,
-    l2_y: String,
-    line_bottom: String,
-    line_top: String,
-    line_editor_open: bool,
-    lines: Vec<Seg>,
-    changes_saved: bool,
+    /// The heights new lines are drawn with.
+    line_bottom: f32,
+    line_top: f32,
+    tool: Tool,
+    line_tool: LineTool,
+    selection: Selection,
+    drag: Option<Drag>,
+    camera: Camera,
+    previous_cursor: Vec2,
+    zoom_to_fit_requested: bool,
+    document: MapDocument,
+    history: History,
+    /// Where the document was loaded from or last saved to.
+    path: Option<PathBuf>,
+    /// Waiting for the user to save or discard their changes.
+    confirm_discard: Option<Discard>,
+    quit_requested: bool,
+    notifications: Notifications,
+    log_open: bool,
+    texture_manifest: Option<TextureManifest>,
 }
 impl State {
+    /// The window title, with an asterisk when there are unsaved
+    /// changes.
+    fn title(&self) -> String {
+        let name = match &self.path {
+            Some(path) => path.file_name().unwrap_or_default().to_string_lossy().into_owned(),
+            None => "Untitled".to_owned(),
+        };
+        let dirty = match self.history.is_dirty() {
+            true => "*",
+            false => "",
+        };
+        format!("{dirty}{name} - {TITLE}")
+    }
+
     pub fn init() -> Self {
         State {
             tools_open: true,
-            l1_x: "0.0".to_owned(),
-            l1_y: "0.0".to_owned(),
-            l2_x: "0.0".to_owned(),
-            l2_y: "0.0".to_owned(),
-            line_bottom: "0.0".to_owned(),
-            line_top: "200.0".to_owned(),
-            line_editor_open: false,
-            lines: vec![],
-            changes_saved: false,
+            line_bottom: 0.0,
+            line_top: 200.0,
+            tool: Tool::Select,
+            line_tool: LineTool::default(),
+            selection: Selection::default(),
+            drag: None,
+            camera: Camera::default(),
+            previous_cursor: Vec2::ZERO,
+            zoom_to_fit_requested: false,
+            document: MapDocument::new(),
+            history: History::default(),
+            path: None,
+            confirm_discard: None,
+            quit_requested: false,
+            notifications: Notifications::default(),
+            log_open: false,
+            texture_manifest: None,
         }
     }
 }
@@ -45,7 +140,7 @@ fn main() -> Result<(), String> {
 
     let win = WindowConfig::new()
         .set_vsync(true)
-        .set_title("RayME - The Rayst Map Editor")
+        .set_title(TITLE)
         .set_window_icon_data(Some(logo))
         .set_taskbar_icon_data(Some(logo))
         .set_lazy_loop(true)
@@ -60,31 +155,451 @@ fn main() -> Result<(), String> {
 }
 
 fn draw(app: &mut App, gfx: &mut Graphics, plugins: &mut Plugins, state: &mut State) {
-    let mut line_renderer = gfx.create_draw();
-    for line in &state.lines {
-        let p1 = line.points().0.xy();
-        let p2 = line.points().1.xy();
-        line_renderer.line(p1.into(), p2.into()).color(Color::WHITE).width(2.0);
-    }
-    
+    let mut pointer_over_ui = false;
+    let mut typing = false;
+    state.notifications.tick(app.timer.delta_f32());
     let mut output = plugins.egui(|ctx| {
         menu_bar(&ctx, state);
         tools_window(&ctx, state);
-        if state.line_editor_open {
-            line_editor_window(&ctx, state)
-        }
+        inspector_window(&ctx, state);
         help_window(&ctx);
         assets_window(&ctx, state);
+        confirm_discard_window(&ctx, state);
+        toasts(&ctx, state);
+        if state.log_open {
+            log_window(&ctx, state);
+        }
+        pointer_over_ui = ctx.wants_pointer_input() || ctx.is_pointer_over_area();
+        typing = ctx.wants_keyboard_input();
     });
 
+    let canvas_size = Vec2::new(gfx.size().0 as f32, gfx.size().1 as f32);
+    if !pointer_over_ui {
+        camera_input(app, state);
+    }
+    state.previous_cursor = app.mouse.position().into();
+    if state.zoom_to_fit_requested {
+        zoom_to_fit(state, canvas_size);
+        state.zoom_to_fit_requested = false;
+    }
+    if !typing {
+        grid_input(app, state);
+        history_input(app, state);
+        file_input(app, state);
+    }
+    if state.quit_requested {
+        app.exit();
+    }
+    let title = state.title();
+    if app.window().title() != title {
+        app.window().set_title(&title);
+    }
+    match state.tool {
+        Tool::Select => select_tool_input(app, state, pointer_over_ui, typing),
+        Tool::DrawLines => line_tool_input(app, state, pointer_over_ui, typing),
+    }
+
+    let camera = state.camera;
+    let mut line_renderer = gfx.create_draw();
     line_renderer.clear(Color::BLACK);
+    if state.document.editor.show_grid {
+        draw_grid(&mut line_renderer, &camera, canvas_size, state.document.editor.grid_size);
+    }
+    for (index, line) in state.document.geometry.segs.iter().enumerate() {
+        let p1 = camera.world_to_screen(line.points().0.xy());
+        let p2 = camera.world_to_screen(line.points().1.xy());
+        let colour = match state.selection.segs.contains(&index) {
+            true => Color::ORANGE,
+            false => Color::WHITE,
+        };
+        line_renderer.line(p1.into(), p2.into()).color(colour).width(2.0);
+    }
+    for (index, thing) in state.document.things.iter().enumerate() {
+        let position = camera.world_to_screen(thing.pos.xy());
+        let colour = match state.selection.things.contains(&index) {
+            true => Color::ORANGE,
+            false => Color::AQUA,
+        };
+        line_renderer.circle(THING_RADIUS).position(position.x, position.y).stroke(2.0).color(colour);
+    }
+    for vertex in &state.selection.vertices {
+        let vertex = camera.world_to_screen(*vertex);
+        line_renderer.circle(4.0).position(vertex.x, vertex.y).color(Color::ORANGE);
+    }
+    if let Some(Drag::Box { start }) = state.drag {
+        let start = camera.world_to_screen(start);
+        let end: Vec2 = app.mouse.position().into();
+        let (min, size) = (start.min(end), (end - start).abs());
+        line_renderer
+            .rect(min.into(), size.into())
+            .stroke(1.0)
+            .color(Color::ORANGE);
+    }
+
+    // The chain being drawn, with a rubber band to the cursor
+    let cursor = placement(app, state);
+    for (p1, p2) in state.line_tool.lines() {
+        let (p1, p2) = (camera.world_to_screen(p1), camera.world_to_screen(p2));
+        line_renderer.line(p1.into(), p2.into()).color(Color::YELLOW).width(2.0);
+    }
+    if let Some((p1, p2)) = state.line_tool.preview(cursor) {
+        let (p1, p2) = (camera.world_to_screen(p1), camera.world_to_screen(p2));
+        line_renderer.line(p1.into(), p2.into()).color(Color::GRAY).width(1.0);
+    }
 
-    if output.needs_repaint() {
+    // Keep drawing while toasts are up, so they go away on time
+    let toasts_showing = state.notifications.toasts().next().is_some();
+    if toasts_showing {
+        app.window().request_frame();
+    }
+
+    if output.needs_repaint() || toasts_showing || state.line_tool.is_drawing() || state.drag.is_some() || app.mouse.middle_is_down() {
         gfx.render(&line_renderer);
         gfx.render(&output);
     }
 }
 
+/// Pan by dragging with the middle mouse button and zoom around
+/// the cursor with the wheel.
+fn camera_input(app: &mut App, state: &mut State) {
+    let cursor: Vec2 = app.mouse.position().into();
+    if app.mouse.middle_is_down() {
+        state.camera.pan(cursor - state.previous_cursor);
+    }
+    if app.mouse.wheel_delta.y != 0.0 {
+        let factor = ZOOM_STEP.powf(app.mouse.wheel_delta.y.signum());
+        state.camera.zoom_at(cursor, factor);
+    }
+}
+
+/// Fit the camera around all of the geometry, or go back to the
+/// starting view if there is none.
+fn zoom_to_fit(state: &mut State, canvas_size: Vec2) {
+    let points = state
+        .document
+        .geometry
+        .segs
+        .iter()
+        .flat_map(|seg| [seg.points().0.xy(), seg.points().1.xy()]);
+    match camera::bounds(points) {
+        Some((min, max)) => state.camera.zoom_to_fit(min, max, canvas_size, FIT_MARGIN),
+        None => state.camera = Camera::default(),
+    }
+}
+
+/// Where a vertex would be placed at the cursor, in world units.
+/// Holding Shift keeps the new line at a multiple of 45 degrees,
+/// otherwise the point snaps to the grid and existing lines.
+fn placement(app: &App, state: &State) -> Vec2 {
+    let cursor = state.camera.screen_to_world(app.mouse.position().into());
+    let radius = state.camera.screen_to_world_distance(SNAP_RADIUS);
+    match state.line_tool.last() {
+        Some(last) if app.keyboard.shift() => snapping::constrain_angle(last, cursor),
+        _ => snapping::snap(cursor, &state.document.editor, &state.document.geometry.segs, radius),
+    }
+}
+
+/// Step the grid size with the bracket keys, and zoom to fit
+/// with Home.
+fn grid_input(app: &mut App, state: &mut State) {
+    let mut settings = state.document.editor.clone();
+    if app.keyboard.was_pressed(KeyCode::RBracket) {
+        settings.grid_size = snapping::grid_step_up(settings.grid_size);
+    }
+    if app.keyboard.was_pressed(KeyCode::LBracket) {
+        settings.grid_size = snapping::grid_step_down(settings.grid_size);
+    }
+    change_editor_settings(state, settings);
+    if app.keyboard.was_pressed(KeyCode::Home) {
+        state.zoom_to_fit_requested = true;
+    }
+}
+
+/// Change the grid and snapping settings through the history, as
+/// they are saved with the map.
+fn change_editor_settings(state: &mut State, after: EditorSettings) {
+    if after != state.document.editor {
+        let command = Command::ChangeEditor { before: state.document.editor.clone(), after };
+        state.history.execute(&mut state.document, command);
+    }
+}
+
+/// Draw the grid lines that are on the canvas. Grids too fine to
+/// see at the current zoom are left out.
+fn draw_grid(draw: &mut Draw, camera: &Camera, canvas_size: Vec2, size: f32) {
+    if size * camera.zoom < 4.0 {
+        return;
+    }
+    let colour = Color::from_rgb(0.15, 0.15, 0.15);
+    let (min, max) = camera.visible(canvas_size);
+    let mut x = (min.x / size).floor() * size;
+    while x <= max.x {
+        let screen_x = camera.world_to_screen(Vec2::new(x, 0.0)).x;
+        draw.line((screen_x, 0.0), (screen_x, canvas_size.y)).color(colour).width(1.0);
+        x += size;
+    }
+    let mut y = (min.y / size).floor() * size;
+    while y <= max.y {
+        let screen_y = camera.world_to_screen(Vec2::new(0.0, y)).y;
+        draw.line((0.0, screen_y), (canvas_size.x, screen_y)).color(colour).width(1.0);
+        y += size;
+    }
+}
+
+/// Click to select, Shift click to add to the selection, drag on
+/// empty space for a box and drag a selection to move it. Delete
+/// removes the selection and the arrow keys nudge it by a grid step.
+fn select_tool_input(app: &mut App, state: &mut State, pointer_over_ui: bool, typing: bool) {
+    let cursor = state.camera.screen_to_world(app.mouse.position().into());
+    let segs = &mut state.document.geometry.segs;
+    let things = &mut state.document.things;
+    let settings = &state.document.editor;
+
+    if !pointer_over_ui && app.mouse.left_was_pressed() {
+        let radius = state.camera.screen_to_world_distance(SNAP_RADIUS);
+        match selection::hit_test(segs, things, cursor, radius) {
+            Some(hit) => {
+                if app.keyboard.shift() {
+                    state.selection.toggle(hit);
+                } else if !state.selection.contains(hit) {
+                    state.selection.clear();
+                    state.selection.insert(hit);
+                }
+                let moving = Moving::new(segs, things, &state.selection);
+                state.drag = Some(Drag::Move { origin: cursor, applied: Vec2::ZERO, moving });
+            }
+            None => {
+                if !app.keyboard.shift() {
+                    state.selection.clear();
+                }
+                state.drag = Some(Drag::Box { start: cursor });
+            }
+        }
+    }
+
+    match state.drag {
+        Some(Drag::Box { start }) if !app.mouse.left_is_down() => {
+            state.selection.extend(selection::box_select(segs, things, start, cursor));
+            state.drag = None;
+        }
+        Some(Drag::Move { .. }) if !app.mouse.left_is_down() => {
+            // The whole drag is undone as one move
+            state.history.seal();
+            state.drag = None;
+        }
+        Some(Drag::Move { origin, ref mut applied, ref moving }) => {
+            let mut total = cursor - origin;
+            if settings.snap_to_grid {
+                total = snapping::snap_to_grid(total, settings.grid_size);
+            }
+            if total != *applied {
+                if let Some(command) = move_selected(segs, things, &mut state.selection, moving, total) {
+                    state.history.record_coalesced(command);
+                }
+                *applied = total;
+            }
+        }
+        _ => (),
+    }
+
+    if typing {
+        return;
+    }
+    if app.keyboard.was_pressed(KeyCode::Delete) || app.keyboard.was_pressed(KeyCode::Back) {
+        let (removed, removed_things) = selection::delete_selection(segs, things, &mut state.selection);
+        let mut commands = vec![];
+        if !removed.is_empty() {
+            commands.push(Command::DeleteSegs(removed));
+        }
+        if !removed_things.is_empty() {
+            commands.push(Command::DeleteThings(removed_things));
+        }
+        if let Some(command) = Command::group(commands) {
+            state.history.record(command);
+        }
+    }
+    if app.keyboard.was_pressed(KeyCode::Escape) {
+        state.selection.clear();
+    }
+    let step = settings.grid_size;
+    let nudges = [
+        (KeyCode::Left, Vec2::new(-step, 0.0)),
+        (KeyCode::Right, Vec2::new(step, 0.0)),
+        (KeyCode::Up, Vec2::new(0.0, -step)),
+        (KeyCode::Down, Vec2::new(0.0, step)),
+    ];
+    for (key, delta) in nudges {
+        if app.keyboard.was_pressed(key) {
+            let moving = Moving::new(segs, things, &state.selection);
+            if let Some(command) = move_selected(segs, things, &mut state.selection, &moving, delta) {
+                state.history.record(command);
+            }
+        }
+    }
+}
+
+/// Move the selection `offset` from where `moving` started it,
+/// giving back the command that did it.
+fn move_selected(
+    segs: &mut [Seg],
+    things: &mut [Thing],
+    selection: &mut Selection,
+    moving: &Moving,
+    offset: Vec2,
+) -> Option<Command> {
+    let ((segs_before, segs_after), (things_before, things_after)) = moving.move_to(segs, things, selection, offset);
+    let mut commands = vec![];
+    if !segs_before.is_empty() {
+        commands.push(Command::ChangeSegs { before: segs_before, after: segs_after });
+    }
+    if !things_before.is_empty() {
+        commands.push(Command::ChangeThings { before: things_before, after: things_after });
+    }
+    Command::group(commands)
+}
+
+/// Ctrl+Z undoes, and Ctrl+Y or Ctrl+Shift+Z redoes.
+fn history_input(app: &mut App, state: &mut State) {
+    if !app.keyboard.ctrl() {
+        return;
+    }
+    let shift = app.keyboard.shift();
+    if app.keyboard.was_pressed(KeyCode::Z) && !shift {
+        undo(state);
+    } else if app.keyboard.was_pressed(KeyCode::Y) || (app.keyboard.was_pressed(KeyCode::Z) && shift) {
+        redo(state);
+    }
+}
+
+/// Undo the last change. The selection is cleared, as the segs it
+/// points at may have moved or gone.
+fn undo(state: &mut State) {
+    if state.drag.is_none() && state.history.undo(&mut state.document) {
+        state.selection.clear();
+    }
+}
+
+/// Redo the last undone change, clearing the selection like `undo`.
+fn redo(state: &mut State) {
+    if state.drag.is_none() && state.history.redo(&mut state.document) {
+        state.selection.clear();
+    }
+}
+
+/// Ctrl+N, Ctrl+O, Ctrl+S and Ctrl+Shift+S do what the File menu
+/// does.
+fn file_input(app: &mut App, state: &mut State) {
+    if !app.keyboard.ctrl() {
+        return;
+    }
+    if app.keyboard.was_pressed(KeyCode::N) {
+        request_discard(state, Discard::New);
+    } else if app.keyboard.was_pressed(KeyCode::O) {
+        request_discard(state, Discard::Open);
+    } else if app.keyboard.was_pressed(KeyCode::S) {
+        match app.keyboard.shift() {
+            true => save_as(state),
+            false => save(state),
+        };
+    }
+}
+
+/// Do something that throws the document away, asking first if
+/// there are unsaved changes.
+fn request_discard(state: &mut State, action: Discard) {
+    match state.history.is_dirty() {
+        true => state.confirm_discard = Some(action),
+        false => discard(state, action),
+    }
+}
+
+fn discard(state: &mut State, action: Discard) {
+    match action {
+        Discard::New => set_document(state, MapDocument::new(), None),
+        Discard::Open => {
+            let file_dialog = rfd::FileDialog::new().add_filter("Map", &["json"]);
+            if let Some(path) = file_dialog.pick_file() {
+                match MapDocument::load(&path) {
+                    Ok(document) => set_document(state, document, Some(path)),
+                    Err(e) => state.notifications.error("Could not open the map", &e),
+                }
+            }
+        }
+        Discard::Quit => state.quit_requested = true,
+    }
+}
+
+/// Replace the open document, starting a fresh history.
+fn set_document(state: &mut State, document: MapDocument, path: Option<PathBuf>) {
+    state.document = document;
+    state.path = path;
+    state.history.clear();
+    state.selection.clear();
+    state.line_tool.cancel();
+    state.drag = None;
+    state.zoom_to_fit_requested = true;
+}
+
+/// Save to where the document came from, or ask where if it is
+/// new. Returns whether it was saved.
+fn save(state: &mut State) -> bool {
+    match state.path.clone() {
+        Some(path) => save_to(state, path),
+        None => save_as(state),
+    }
+}
+
+/// Ask where to save the document and save it there. Returns
+/// whether it was saved.
+fn save_as(state: &mut State) -> bool {
+    let file_dialog = rfd::FileDialog::new().add_filter("Map", &["json"]);
+    match file_dialog.save_file() {
+        Some(path) => save_to(state, path),
+        None => false,
+    }
+}
+
+fn save_to(state: &mut State, path: PathBuf) -> bool {
+    match state.document.save(&path) {
+        Ok(_) => {
+            state.notifications.info(format!("Saved {}", path.display()));
+            state.history.mark_saved();
+            state.path = Some(path);
+            true
+        }
+        Err(e) => {
+            state.notifications.error("Could not save the map", &e);
+            false
+        }
+    }
+}
+
+/// Place vertices with the mouse while the line tool is active.
+/// Clicks on the ui and key presses while typing are ignored.
+fn line_tool_input(app: &mut App, state: &mut State, pointer_over_ui: bool, typing: bool) {
+    if !pointer_over_ui {
+        if app.mouse.left_was_pressed() {
+            let point = placement(app, state);
+            state.line_tool.click(point);
+        }
+        if app.mouse.right_was_pressed() {
+            let (bottom, top) = (state.line_bottom, state.line_top);
+            let lines: Vec<Seg> = state.line_tool
+                .finish()
+                .into_iter()
+                .map(|(p1, p2)| Seg::new((p1.extend(bottom), p2.extend(bottom)), top - bottom))
+                .collect();
+            if !lines.is_empty() {
+                let at = state.document.geometry.segs.len();
+                state.history.execute(&mut state.document, Command::AddSegs { at, segs: lines });
+            }
+        }
+    }
+    if !typing && app.keyboard.was_pressed(KeyCode::Escape) {
+        state.line_tool.cancel();
+    }
+}
+
 fn menu_bar(ctx: &Context, state: &mut State) {
     egui::TopBottomPanel::top("File Options Banner")
     .resizable(false)
@@ -92,102 +607,78 @@ fn menu_bar(ctx: &Context, state: &mut State) {
     .show(&ctx, |ui| {
         ui.with_layout(egui::Layout::left_to_right(Align::Center), |ui| {
             ui.menu_button("File", |ui| {
-                ui.button("New");
-                if ui.button("Save").clicked() {
-                    let file_dialog = rfd::FileDialog::new();
-                    let path = file_dialog.save_file();
-                    match path {
-                        None => {
-                            egui::Window::new("Error E001")
-                                .collapsible(false)
-                                .resizable(false)
-                                .show(&ctx, |ui| {
-                                    ui.colored_label(Color32::RED, "An error occured getting path.");
-                                });
-                        },
-                        Some(path) => {
-                            let json = serde_json::to_string_pretty(&state.lines).unwrap();
-                            match write(path, json) {
-                                Ok(_) => (),
-                                Err(_) => {egui::Window::new("Error E002")
-                                    .collapsible(false)
-                                    .resizable(false)
-                                    .show(&ctx, |ui| {
-                                        ui.colored_label(Color32::RED, "File save failed")
-                                    });},
-                            }
-                        }
-                    }
-                };
-                if ui.button("Open").clicked() {
-                    let file_dialog = rfd::FileDialog::new();
-                    let path = file_dialog.pick_file();
-                    match path {
-                        None => {
-                            egui::Window::new("Error E003")
-                                .collapsible(false)
-                                .resizable(false)
-                                .show(&ctx, |ui| {
-                                    ui.colored_label(Color32::RED, "File select failed")
-                                });
-                        },
-                        Some(path) => {
-                            match read(path) {
-                                Ok(data) => {
-                                    let reader = BufReader::new(data.as_slice());
-                                    let segs: Vec<Seg> = serde_json::from_reader(reader).unwrap();
-                                    state.lines = segs;
-                                },
-                                Err(_) => {
-                                    egui::Window::new("Error E003")
-                                        .collapsible(false)
-                                        .resizable(false)
-                                        .show(&ctx, |ui| {
-                                            ui.colored_label(Color32::RED, "File load failed")
-                                        });
-                                }
-                            }
-                        }
-                    }
-                };
+                if ui.button("New (Ctrl+N)").clicked() {
+                    request_discard(state, Discard::New);
+                    ui.close_menu();
+                }
+                if ui.button("Open (Ctrl+O)").clicked() {
+                    request_discard(state, Discard::Open);
+                    ui.close_menu();
+                }
+                if ui.button("Save (Ctrl+S)").clicked() {
+                    save(state);
+                    ui.close_menu();
+                }
+                if ui.button("Save As (Ctrl+Shift+S)").clicked() {
+                    save_as(state);
+                    ui.close_menu();
+                }
+                ui.separator();
+                if ui.button("Quit").clicked() {
+                    request_discard(state, Discard::Quit);
+                    ui.close_menu();
+                }
+            });
+
+            ui.menu_button("Edit", |ui| {
+                if ui.add_enabled(state.history.can_undo(), egui::Button::new("Undo (Ctrl+Z)")).clicked() {
+                    undo(state);
+                }
+                if ui.add_enabled(state.history.can_redo(), egui::Button::new("Redo (Ctrl+Y)")).clicked() {
+                    redo(state);
+                }
+                ui.separator();
+                let mut depth = state.history.depth();
+                let steps = egui::DragValue::new(&mut depth).clamp_range(1..=1000).prefix("Undo steps: ");
+                if ui.add(steps).changed() {
+                    state.history.set_depth(depth);
+                }
             });
 
             ui.menu_button("Compile", |ui| {
                 if ui.button("Compile").clicked() {
-                    let file_dialog = rfd::FileDialog::new();
-                    let path = file_dialog.save_file();
-                    match path {
-                        None => {
-                            egui::Window::new("Error E001")
-                                .collapsible(false)
-                                .resizable(false)
-                                .show(&ctx, |ui| {
-                                    ui.colored_label(Color32::RED, "An error occured getting path.");
-                                });
-                        },
-                        Some(path) => {
-                            ui.spinner();
-                            let partitioned = non_recursive_partition(state.lines.clone());
-                            let json = serde_json::to_string_pretty(&partitioned);
-                            match write(path, json.unwrap()) {
-                                Ok(_) => (),
-                                Err(_) => {
-                                    egui::Window::new("Error E004")
-                                        .collapsible(false)
-                                        .resizable(false)
-                                        .show(&ctx, |ui| {
-                                            ui.colored_label(Color32::RED, "Compilation error")
-                                        });
-                                }
-                            }
+                    if state.document.geometry.segs.is_empty() {
+                        state.notifications.warning("The map has no lines to compile");
+                    } else if let Some(path) = rfd::FileDialog::new().save_file() {
+                        match compile_to(&state.document, &path) {
+                            Ok(_) => state.notifications.info(format!("Compiled to {}", path.display())),
+                            Err(e) => state.notifications.error("Could not compile the map", &e),
                         }
                     }
+                    ui.close_menu();
                 };
             });
+
+            let errors = state.notifications.error_count();
+            let log = match errors {
+                0 => "Log".to_owned(),
+                _ => format!("Log ({errors})"),
+            };
+            if ui.selectable_label(state.log_open, log).clicked() {
+                state.log_open = !state.log_open;
+            }
         });
     });
 }
 
+/// Partition the geometry of a document and write it out for the engine.
+fn compile_to(document: &MapDocument, path: &Path) -> anyhow::Result<()> {
+    let partitioned = compile_document(document).context("Failed to partition the map")?;
+    write(path, serde_json::to_string_pretty(&partitioned)?)
+        .with_context(|| format!("Failed to write {}", path.display()))?;
+    Ok(())
+}
+
 fn tools_window(ctx: &Context, state: &mut State) {
     egui::Window::new("Tools")
     .anchor(Align2::LEFT_TOP, [0.0, 0.0])
@@ -197,96 +688,456 @@ fn tools_window(ctx: &Context, state: &mut State) {
         ui.label("This window lets you select the tools you need for making a map.");
         ui.separator();
 
-        if ui.button("⬈").on_hover_text("Line Drawer Parametric Interface").clicked() {
-            state.line_editor_open = match state.line_editor_open {
-                true => false,
-                false => true,
-            };
+        ui.horizontal(|ui| {
+            let tools = [
+                (Tool::Select, "⬉", "Select, move and delete lines and vertices."),
+                (Tool::DrawLines, "✏", "Draw lines with the mouse. Right click to finish, Escape to cancel."),
+            ];
+            for (tool, icon, hover) in tools {
+                if ui.selectable_label(state.tool == tool, icon).on_hover_text(hover).clicked() {
+                    state.tool = tool;
+                    state.line_tool.cancel();
+                    state.drag = None;
+                }
+            }
+        });
+        if state.tool == Tool::DrawLines {
+            ui.horizontal(|ui| {
+                ui.label("Bottom");
+                ui.add(egui::DragValue::new(&mut state.line_bottom));
+                ui.label("Top");
+                ui.add(egui::DragValue::new(&mut state.line_top));
+            });
         }
-    });
-}
+        ui.separator();
 
-fn line_editor_window(ctx: &Context, state: &mut State) {
-    egui::Window::new("Parametric Line Drawer")
-    .default_width(200.0)
-    .resizable(false)
-    .show(&ctx, |ui| {
-        ui.label("This window lets you specify line coordinates then draw them to the screen.");
+        let mut settings = state.document.editor.clone();
+        ui.horizontal(|ui| {
+            if ui.button("-").on_hover_text("Smaller grid ([)").clicked() {
+                settings.grid_size = snapping::grid_step_down(settings.grid_size);
+            }
+            ui.label(format!("Grid {}", settings.grid_size));
+            if ui.button("+").on_hover_text("Larger grid (])").clicked() {
+                settings.grid_size = snapping::grid_step_up(settings.grid_size);
+            }
+        });
+        ui.checkbox(&mut settings.show_grid, "Show grid");
+        ui.checkbox(&mut settings.snap_to_grid, "Snap to grid");
+        ui.checkbox(&mut settings.snap_to_vertices, "Snap to vertices");
+        ui.checkbox(&mut settings.snap_to_midpoints, "Snap to midpoints");
+        change_editor_settings(state, settings);
+        ui.label("Hold Shift to draw at 45° steps.");
         ui.separator();
 
-        ui.label("Line first point x");
-        ui.text_edit_singleline(&mut state.l1_x);
-        if state.l1_x.parse::<f32>().is_err() {
-            ui.colored_label(Color32::RED, "Coordinate must be a numeric value.");
+        ui.label(format!("Zoom {:.0}%", state.camera.zoom * 100.0));
+        if ui.button("Zoom to fit").on_hover_text("Show the whole map (Home)").clicked() {
+            state.zoom_to_fit_requested = true;
         }
+    });
+}
 
-        ui.label("Line first point y");
-        ui.text_edit_singleline(&mut state.l1_y);
-        if state.l1_y.parse::<f32>().is_err() {
-            ui.colored_label(Color32::RED, "Coordinate must be a numeric value.");
-        }
+/// The last property edited in the inspector this frame, to make
+/// to every selected item.
+type Edit<T> = Option<Box<dyn Fn(&mut T)>>;
+
+/// Show and edit the properties of the selection. With nothing
+/// selected it edits the map's own details instead.
+fn inspector_window(ctx: &Context, state: &mut State) {
+    egui::Window::new("Inspector")
+        .anchor(Align2::RIGHT_CENTER, [0.0, 0.0])
+        .default_width(220.0)
+        .resizable(true)
+        .show(ctx, |ui| {
+            egui::ScrollArea::vertical().show(ui, |ui| {
+                if state.selection.is_empty() {
+                    metadata_properties(ui, state);
+                    return;
+                }
+                if !state.selection.segs.is_empty() {
+                    ui.heading(format!("Lines ({})", state.selection.segs.len()));
+                    seg_properties(ui, state);
+                    let sectors = inspector::selected_sectors(
+                        &state.document.geometry.segs,
+                        state.document.geometry.sectors.len(),
+                        &state.selection,
+                    );
+                    if !sectors.is_empty() {
+                        ui.separator();
+                        ui.heading(format!("Sectors ({})", sectors.len()));
+                        sector_properties(ui, state, &sectors);
+                    }
+                }
+                if !state.selection.things.is_empty() {
+                    ui.separator();
+                    ui.heading(format!("Things ({})", state.selection.things.len()));
+                    thing_properties(ui, state);
+                }
+                if state.selection.segs.is_empty() && state.selection.things.is_empty() {
+                    ui.label(format!("{} vertices selected.", state.selection.vertices.len()));
+                }
+            });
+        });
+}
+
+fn seg_properties(ui: &mut Ui, state: &mut State) {
+    let indices = state.selection.segs.clone();
+    let segs = &state.document.geometry.segs;
+    let values = || indices.iter().filter_map(|index| segs.get(*index));
+    let start_x = Shared::of(values().map(|seg| seg.points().0.x));
+    let start_y = Shared::of(values().map(|seg| seg.points().0.y));
+    let end_x = Shared::of(values().map(|seg| seg.points().1.x));
+    let end_y = Shared::of(values().map(|seg| seg.points().1.y));
+    let bottom = Shared::of(values().map(|seg| seg.points().0.z));
+    let top = Shared::of(values().map(|seg| seg.points().0.z + seg.height()));
+    let texture = Shared::of(values().map(|seg| seg.texture()));
+    let offset_x = Shared::of(values().map(|seg| seg.texture_offset().x));
+    let offset_y = Shared::of(values().map(|seg| seg.texture_offset().y));
+    let flags = Shared::of(values().map(|seg| seg.flags()));
+    let sector = Shared::of(values().map(|seg| seg.sector()));
 
-        ui.label("Line second point x");
-        ui.text_edit_singleline(&mut state.l2_x);
-        if state.l2_x.parse::<f32>().is_err() {
-            ui.colored_label(Color32::RED, "Coordinate must be a numeric value.");
+    let mut edit: Edit<Seg> = None;
+    let history = &mut state.history;
+    if let Some(x) = drag_property(ui, history, "Start x", start_x) {
+        edit = Some(Box::new(move |seg| *seg = seg.with_points((Vec3::new(x, seg.points().0.y, seg.points().0.z), seg.points().1))));
+    }
+    if let Some(y) = drag_property(ui, history, "Start y", start_y) {
+        edit = Some(Box::new(move |seg| *seg = seg.with_points((Vec3::new(seg.points().0.x, y, seg.points().0.z), seg.points().1))));
+    }
+    if let Some(x) = drag_property(ui, history, "End x", end_x) {
+        edit = Some(Box::new(move |seg| *seg = seg.with_points((seg.points().0, Vec3::new(x, seg.points().1.y, seg.points().1.z)))));
+    }
+    if let Some(y) = drag_property(ui, history, "End y", end_y) {
+        edit = Some(Box::new(move |seg| *seg = seg.with_points((seg.points().0, Vec3::new(seg.points().1.x, y, seg.points().1.z)))));
+    }
+    if let Some(bottom) = drag_property(ui, history, "Bottom", bottom) {
+        // Keep the top where it is
+        edit = Some(Box::new(move |seg| {
+            let (mut p1, mut p2) = seg.points();
+            let top = p1.z + seg.height();
+            (p1.z, p2.z) = (bottom, bottom);
+            *seg = seg.with_points((p1, p2)).with_height(top - bottom);
+        }));
+    }
+    if let Some(top) = drag_property(ui, history, "Top", top) {
+        edit = Some(Box::new(move |seg| *seg = seg.with_height(top - seg.points().0.z)));
+    }
+    if let Some(texture) = texture_property(ui, history, &mut state.notifications, "Texture", texture) {
+        edit = Some(Box::new(move |seg| *seg = seg.with_texture(texture)));
+    }
+    if let Some(x) = drag_property(ui, history, "Offset x", offset_x) {
+        edit = Some(Box::new(move |seg| *seg = seg.with_texture_offset(Vec2::new(x, seg.texture_offset().y))));
+    }
+    if let Some(y) = drag_property(ui, history, "Offset y", offset_y) {
+        edit = Some(Box::new(move |seg| *seg = seg.with_texture_offset(Vec2::new(seg.texture_offset().x, y))));
+    }
+    if let Some(flags) = drag_property(ui, history, "Flags", flags) {
+        edit = Some(Box::new(move |seg| *seg = seg.with_flags(flags)));
+    }
+
+    // Which sector the lines face into, or a new one
+    let sector_count = state.document.geometry.sectors.len();
+    let sector_name = |sector: Option<usize>| sector.map_or("None".to_owned(), |sector| format!("Sector {sector}"));
+    let mut chosen = None;
+    ui.horizontal(|ui| {
+        ui.label("Sector");
+        let text = match sector {
+            Shared::Mixed(_) => "Mixed".to_owned(),
+            _ => sector_name(sector.value().flatten()),
+        };
+        egui::ComboBox::from_id_source("Seg sector").selected_text(text).show_ui(ui, |ui| {
+            for option in std::iter::once(None).chain((0..sector_count).map(Some)) {
+                if ui.selectable_label(sector == Shared::Same(option), sector_name(option)).clicked() {
+                    chosen = Some(option);
+                }
+            }
+        });
+        if ui.button("New").on_hover_text("Put the lines in a new sector").clicked() {
+            let segs = &state.document.geometry.segs;
+            let commands = [
+                Some(Command::AddSector(Sector::default())),
+                inspector::edit_segs(segs, &indices, |seg| *seg = seg.with_sector(Some(sector_count))),
+            ];
+            if let Some(command) = Command::group(commands.into_iter().flatten().collect()) {
+                state.history.execute(&mut state.document, command);
+            }
         }
+    });
+    if let Some(sector) = chosen {
+        state.history.seal();
+        edit = Some(Box::new(move |seg| *seg = seg.with_sector(sector)));
+    }
 
-        ui.label("Line second point y");
-        ui.text_edit_singleline(&mut state.l2_y);
-        if state.l2_y.parse::<f32>().is_err() {
-            ui.colored_label(Color32::RED, "Coordinate must be a numeric value.");
+    if let Some(edit) = edit {
+        if let Some(command) = inspector::edit_segs(&state.document.geometry.segs, &indices, edit) {
+            state.history.execute_coalesced(&mut state.document, command);
         }
+    }
+}
+
+fn sector_properties(ui: &mut Ui, state: &mut State, indices: &BTreeSet<usize>) {
+    let sectors = &state.document.geometry.sectors;
+    let values = || indices.iter().map(|index| &sectors[*index]);
+    let floor = Shared::of(values().map(|sector| sector.floor_height));
+    let ceiling = Shared::of(values().map(|sector| sector.ceiling_height));
+    let floor_texture = Shared::of(values().map(|sector| sector.floor_texture));
+    let ceiling_texture = Shared::of(values().map(|sector| sector.ceiling_texture));
+    let light = Shared::of(values().map(|sector| sector.light_level));
+    let special = Shared::of(values().map(|sector| sector.special));
 
-        ui.label("Line bottom coordinate");
-        ui.text_edit_singleline(&mut state.line_bottom);
-        if state.line_bottom.parse::<f32>().is_err() {
-            ui.colored_label(Color32::RED, "Coordinate must be a numeric value.");
+    let mut edit: Edit<Sector> = None;
+    let history = &mut state.history;
+    if let Some(height) = drag_property(ui, history, "Floor height", floor) {
+        edit = Some(Box::new(move |sector| sector.floor_height = height));
+    }
+    if let Some(height) = drag_property(ui, history, "Ceiling height", ceiling) {
+        edit = Some(Box::new(move |sector| sector.ceiling_height = height));
+    }
+    let notifications = &mut state.notifications;
+    if let Some(texture) = texture_property(ui, history, notifications, "Floor texture", floor_texture) {
+        edit = Some(Box::new(move |sector| sector.floor_texture = texture));
+    }
+    if let Some(texture) = texture_property(ui, history, notifications, "Ceiling texture", ceiling_texture) {
+        edit = Some(Box::new(move |sector| sector.ceiling_texture = texture));
+    }
+    if let Some(light) = drag_property(ui, history, "Light level", light) {
+        edit = Some(Box::new(move |sector| sector.light_level = light));
+    }
+    if let Some(special) = drag_property(ui, history, "Special", special) {
+        edit = Some(Box::new(move |sector| sector.special = special));
+    }
+
+    if let Some(edit) = edit {
+        if let Some(command) = inspector::edit_sectors(&state.document.geometry.sectors, indices, edit) {
+            state.history.execute_coalesced(&mut state.document, command);
         }
+    }
+}
 
-        ui.label("Line top coordinate");
-        ui.text_edit_singleline(&mut state.line_top);
-        if state.line_top.parse::<f32>().is_err() {
-            ui.colored_label(Color32::RED, "Coordinate must be a numeric value.");
+fn thing_properties(ui: &mut Ui, state: &mut State) {
+    let indices = state.selection.things.clone();
+    let things = &state.document.things;
+    let values = || indices.iter().filter_map(|index| things.get(*index));
+    let x = Shared::of(values().map(|thing| thing.pos.x));
+    let y = Shared::of(values().map(|thing| thing.pos.y));
+    let kind = Shared::of(values().map(|thing| thing.kind));
+    let angle = Shared::of(values().map(|thing| thing.angle_deg));
+    let flags = Shared::of(values().map(|thing| thing.flags));
+
+    let mut edit: Edit<Thing> = None;
+    let history = &mut state.history;
+    if let Some(x) = drag_property(ui, history, "x", x) {
+        edit = Some(Box::new(move |thing| thing.pos.x = x));
+    }
+    if let Some(y) = drag_property(ui, history, "y", y) {
+        edit = Some(Box::new(move |thing| thing.pos.y = y));
+    }
+    if let Some(kind) = drag_property(ui, history, "Type", kind) {
+        edit = Some(Box::new(move |thing| thing.kind = kind));
+    }
+    if let Some(angle) = drag_property(ui, history, "Angle", angle) {
+        edit = Some(Box::new(move |thing| thing.angle_deg = angle.rem_euclid(360.0)));
+    }
+    if let Some(flags) = drag_property(ui, history, "Flags", flags) {
+        edit = Some(Box::new(move |thing| thing.flags = flags));
+    }
+
+    if let Some(edit) = edit {
+        if let Some(command) = inspector::edit_things(&state.document.things, &indices, edit) {
+            state.history.execute_coalesced(&mut state.document, command);
         }
+    }
+}
 
-        let is_invalid_line = {
-            state.l1_x.parse::<f32>().is_err() |
-            state.l1_y.parse::<f32>().is_err() |
-            state.l2_x.parse::<f32>().is_err() |
-            state.l2_y.parse::<f32>().is_err() |
-            state.line_bottom.parse::<f32>().is_err() |
-            state.line_top.parse::<f32>().is_err()
-        };
+fn metadata_properties(ui: &mut Ui, state: &mut State) {
+    ui.heading("Map");
+    let metadata = &state.document.metadata;
+    let mut after = metadata.clone();
+    let history = &mut state.history;
+    if let Some(name) = text_property(ui, history, "Name", Shared::Same(metadata.name.clone()), None) {
+        after.name = name;
+    }
+    if let Some(author) = text_property(ui, history, "Author", Shared::Same(metadata.author.clone()), None) {
+        after.author = author;
+    }
+    // Empty means none
+    let optional = |text: String| Some(text).filter(|text| !text.is_empty());
+    let sky = Shared::Same(metadata.sky.unwrap_or_default());
+    if let Some(sky) = texture_property(ui, history, &mut state.notifications, "Sky", sky) {
+        after.sky = Some(sky).filter(|sky| !sky.is_empty());
+    }
+    let music = Shared::Same(metadata.music.clone().unwrap_or_default());
+    if let Some(music) = text_property(ui, history, "Music", music, None) {
+        after.music = optional(music);
+    }
 
-        let mut button_enabled = true; 
-        if is_invalid_line {
-            button_enabled = false;
-            ui.colored_label(Color32::RED, "Please amend the above fields");
+    if after != *metadata {
+        let command = Command::ChangeMetadata { before: metadata.clone(), after };
+        state.history.execute_coalesced(&mut state.document, command);
+    }
+    ui.separator();
+    ui.label("Select lines or things to edit them.");
+}
+
+/// A number shared by the selection, which is dragged or typed.
+/// Returns the new value if it was changed. Once the drag or
+/// typing is done the change is sealed, so it is undone in one go.
+fn drag_property<T: egui::emath::Numeric>(ui: &mut Ui, history: &mut History, label: &str, shared: Shared<T>) -> Option<T> {
+    let mut value = shared.value()?;
+    let response = ui.horizontal(|ui| {
+        ui.label(label);
+        let response = ui.add(egui::DragValue::new(&mut value));
+        if shared.is_mixed() {
+            ui.weak("mixed");
         }
+        response
+    }).inner;
+    if response.drag_released() || response.lost_focus() {
+        history.seal();
+    }
+    response.changed().then_some(value)
+}
 
-        if ui.add_enabled(
-            button_enabled, 
-            egui::Button::new("Draw Line")
-        ).clicked() { 
-            let fl1_x = state.l1_x.parse::<f32>().unwrap();
-            let fl1_y = state.l1_y.parse::<f32>().unwrap();
-            let fl2_x = state.l2_x.parse::<f32>().unwrap();
-            let fl2_y = state.l2_y.parse::<f32>().unwrap();
-            let fl_bottom = state.line_bottom.parse::<f32>().unwrap();
-            let fl_top = state.line_top.parse::<f32>().unwrap();
-
-            let line = Seg::new(
-                (
-                    Vec3::from((fl1_x, fl1_y, fl_bottom)),
-                    Vec3::from((fl2_x, fl2_y, fl_bottom))
-                ), 
-                fl_top - fl_bottom
-            );
-
-            state.lines.push(line);
+/// Text shared by the selection, like `drag_property`. Mixed text
+/// starts out empty.
+fn text_property(ui: &mut Ui, history: &mut History, label: &str, shared: Shared<String>, limit: Option<usize>) -> Option<String> {
+    let mixed = shared.is_mixed();
+    let mut text = match mixed {
+        true => String::new(),
+        false => shared.value()?,
+    };
+    let response = ui.horizontal(|ui| {
+        ui.label(label);
+        let mut edit = egui::TextEdit::singleline(&mut text).desired_width(120.0);
+        if mixed {
+            edit = edit.hint_text("mixed");
         }
-    });
+        if let Some(limit) = limit {
+            edit = edit.char_limit(limit);
+        }
+        ui.add(edit)
+    }).inner;
+    if response.lost_focus() {
+        history.seal();
+    }
+    response.changed().then_some(text)
+}
+
+/// A texture name shared by the selection, like `text_property`.
+/// Names that are not valid texture names are not applied, and the
+/// reason is shown instead.
+fn texture_property(
+    ui: &mut Ui,
+    history: &mut History,
+    notifications: &mut Notifications,
+    label: &str,
+    shared: Shared<TextureName>,
+) -> Option<TextureName> {
+    // The text box counts characters, not bytes, so it cannot stop
+    // every name that is too long
+    let text = text_property(ui, history, label, shared.map(String::from), Some(TextureName::MAX_LEN))?;
+    match TextureName::new(&text) {
+        Ok(name) => Some(name),
+        Err(e) => {
+            notifications.error("Invalid texture name", &e);
+            None
+        }
+    }
+}
+
+/// Ask whether to save changes before they are thrown away.
+fn confirm_discard_window(ctx: &Context, state: &mut State) {
+    let Some(action) = state.confirm_discard else {
+        return;
+    };
+    egui::Window::new("Unsaved Changes")
+        .anchor(Align2::CENTER_CENTER, [0.0, 0.0])
+        .collapsible(false)
+        .resizable(false)
+        .show(ctx, |ui| {
+            let name = match &state.path {
+                Some(path) => path.display().to_string(),
+                None => "the map".to_owned(),
+            };
+            ui.label(format!("Save changes to {name} first?"));
+            ui.horizontal(|ui| {
+                if ui.button("Save").clicked() {
+                    state.confirm_discard = None;
+                    if save(state) {
+                        discard(state, action);
+                    }
+                }
+                if ui.button("Don't Save").clicked() {
+                    state.confirm_discard = None;
+                    discard(state, action);
+                }
+                if ui.button("Cancel").clicked() {
+                    state.confirm_discard = None;
+                }
+            });
+        });
+}
+
+/// Show recent messages in the bottom right corner. Clicking one
+/// closes it.
+fn toasts(ctx: &Context, state: &mut State) {
+    let mut dismissed = None;
+    egui::Area::new("Toasts")
+        .anchor(Align2::RIGHT_BOTTOM, [-8.0, -8.0])
+        .show(ctx, |ui| {
+            for (index, toast) in state.notifications.toasts().enumerate() {
+                let response = egui::Frame::popup(ui.style())
+                    .show(ui, |ui| ui.colored_label(level_colour(toast.level), &toast.message))
+                    .response;
+                let hover = toast.details.as_deref().unwrap_or("Click to close");
+                if response.interact(Sense::click()).on_hover_text(hover).clicked() {
+                    dismissed = Some(index);
+                }
+            }
+        });
+    if let Some(index) = dismissed {
+        state.notifications.dismiss(index);
+    }
+}
+
+/// Every message since the editor started, with the details of
+/// errors.
+fn log_window(ctx: &Context, state: &mut State) {
+    let mut open = true;
+    egui::Window::new("Log")
+        .open(&mut open)
+        .default_width(400.0)
+        .resizable(true)
+        .show(ctx, |ui| {
+            if ui.button("Clear").clicked() {
+                state.notifications.clear_log();
+            }
+            ui.separator();
+            egui::ScrollArea::vertical().stick_to_bottom(true).show(ui, |ui| {
+                for (index, notification) in state.notifications.log().enumerate() {
+                    let line = format!("[{:.1}s] {}", notification.time, notification.message);
+                    let colour = level_colour(notification.level);
+                    match &notification.details {
+                        Some(details) => {
+                            egui::CollapsingHeader::new(RichText::new(line).color(colour))
+                                .id_source(index)
+                                .show(ui, |ui| ui.label(details));
+                        }
+                        None => {
+                            ui.colored_label(colour, line);
+                        }
+                    }
+                }
+            });
+        });
+    state.log_open = open;
+}
+
+fn level_colour(level: Level) -> Color32 {
+    match level {
+        Level::Info => Color32::LIGHT_GRAY,
+        Level::Warning => Color32::YELLOW,
+        Level::Error => Color32::RED,
+    }
 }
 
 fn help_window(ctx: &Context) {
@@ -319,6 +1170,24 @@ fn assets_window(ctx: &Context, state: &mut State) {
             });
             ui.collapsing("Textures", |ui| {
                 ui.label("This is where the textures library is.");
+                if ui.button("Open Manifest").clicked() {
+                    let file_dialog = rfd::FileDialog::new().add_filter("Texture Manifest", &["json"]);
+                    if let Some(path) = file_dialog.pick_file() {
+                        match TextureManifest::load(&path) {
+                            Ok(manifest) => {
+                                state.notifications.info(format!("Loaded {}", path.display()));
+                                state.texture_manifest = Some(manifest);
+                            },
+                            Err(e) => state.notifications.error("Could not load the texture manifest", &e),
+                        }
+                    }
+                }
+                if let Some(manifest) = &state.texture_manifest {
+                    ui.separator();
+                    for entry in &manifest.textures {
+                        ui.label(format!("{} ({:?})", entry.name, entry.kind));
+                    }
+                }
             });
             ui.collapsing("Entities", |ui| {
                 ui.label("This is where the Entity Parts are put together.");
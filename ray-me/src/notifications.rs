@@ -0,0 +1,162 @@
+//! Messages for the user. Each one pops up as a toast for a few
+//! seconds and is also kept in a log, errors along with the
+//! detail of what went wrong, so nothing is lost when the toast
+//! goes away.
+
+use std::collections::VecDeque;
+
+/// How long toasts stay up for, in seconds. Errors stay longer.
+pub const TOAST_SECONDS: f32 = 4.0;
+pub const ERROR_TOAST_SECONDS: f32 = 8.0;
+
+/// The most toasts shown at once. Older ones are dropped first.
+pub const MAX_TOASTS: usize = 5;
+
+/// The most messages kept in the log.
+pub const MAX_LOG: usize = 500;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Level {
+    Info,
+    Warning,
+    Error,
+}
+
+/// A message, with when it was sent in seconds since the editor
+/// started.
+#[derive(Clone, PartialEq, Debug)]
+pub struct Notification {
+    pub level: Level,
+    pub message: String,
+    /// The underlying errors, outermost first, one per line.
+    pub details: Option<String>,
+    pub time: f32,
+}
+
+/// The toasts showing and the log of every message.
+#[derive(Clone, Default, Debug)]
+pub struct Notifications {
+    time: f32,
+    /// Toasts showing, oldest first, with the time they go away.
+    toasts: VecDeque<(Notification, f32)>,
+    log: VecDeque<Notification>,
+}
+impl Notifications {
+    pub fn info<S: Into<String>>(&mut self, message: S) {
+        self.push(Level::Info, message.into(), None);
+    }
+
+    pub fn warning<S: Into<String>>(&mut self, message: S) {
+        self.push(Level::Warning, message.into(), None);
+    }
+
+    /// Report an error, keeping the chain of causes behind it as
+    /// the details.
+    pub fn error<S: Into<String>>(&mut self, message: S, error: &anyhow::Error) {
+        let details = error.chain().map(ToString::to_string).collect::<Vec<_>>().join("\n");
+        self.push(Level::Error, message.into(), Some(details));
+    }
+
+    /// Move time on, dropping toasts that have been up long enough.
+    pub fn tick(&mut self, delta: f32) {
+        self.time += delta;
+        let time = self.time;
+        self.toasts.retain(|(_, expires)| *expires > time);
+    }
+
+    /// The toasts to show, oldest first.
+    pub fn toasts(&self) -> impl Iterator<Item = &Notification> {
+        self.toasts.iter().map(|(notification, _)| notification)
+    }
+
+    /// Close a toast early. It stays in the log.
+    pub fn dismiss(&mut self, index: usize) {
+        self.toasts.remove(index);
+    }
+
+    /// Every message kept, oldest first.
+    pub fn log(&self) -> impl Iterator<Item = &Notification> {
+        self.log.iter()
+    }
+
+    /// The number of errors in the log.
+    pub fn error_count(&self) -> usize {
+        self.log.iter().filter(|notification| notification.level == Level::Error).count()
+    }
+
+    pub fn clear_log(&mut self) {
+        self.log.clear();
+    }
+
+    fn push(&mut self, level: Level, message: String, details: Option<String>) {
+        let notification = Notification {
+            level,
+            message,
+            details,
+            time: self.time,
+        };
+        let duration = match level {
+            Level::Error => ERROR_TOAST_SECONDS,
+            _ => TOAST_SECONDS,
+        };
+        self.toasts.push_back((notification.clone(), self.time + duration));
+        if self.toasts.len() > MAX_TOASTS {
+            self.toasts.pop_front();
+        }
+        self.log.push_back(notification);
+        if self.log.len() > MAX_LOG {
+            self.log.pop_front();
+        }
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use crate::notifications::*;
+    use anyhow::{anyhow, Context};
+
+    #[test]
+    fn toasts_expire() {
+        let mut notifications = Notifications::default();
+        notifications.info("Saved");
+        notifications.tick(1.0);
+        notifications.error("Save failed", &anyhow!("disk full"));
+
+        notifications.tick(TOAST_SECONDS);
+        let toasts: Vec<_> = notifications.toasts().map(|toast| toast.message.as_str()).collect();
+        assert!(toasts == vec!["Save failed"]);
+        notifications.tick(ERROR_TOAST_SECONDS);
+        assert!(notifications.toasts().count() == 0);
+
+        // The log keeps everything
+        assert!(notifications.log().count() == 2);
+        assert!(notifications.error_count() == 1);
+    }
+
+    #[test]
+    fn error_details() {
+        let mut notifications = Notifications::default();
+        let error = Err::<(), _>(anyhow!("expected value at line 1"))
+            .context("Failed to parse maps/e1m1.json")
+            .unwrap_err();
+        notifications.error("Could not open the map", &error);
+        let logged = notifications.log().next().unwrap();
+        assert!(logged.details.as_deref() == Some("Failed to parse maps/e1m1.json\nexpected value at line 1"));
+    }
+
+    #[test]
+    fn limits() {
+        let mut notifications = Notifications::default();
+        for i in 0..MAX_LOG + 1 {
+            notifications.warning(format!("Warning {i}"));
+        }
+        assert!(notifications.toasts().count() == MAX_TOASTS);
+        assert!(notifications.log().count() == MAX_LOG);
+        assert!(notifications.log().next().unwrap().message == "Warning 1");
+
+        notifications.dismiss(0);
+        assert!(notifications.toasts().count() == MAX_TOASTS - 1);
+        notifications.clear_log();
+        assert!(notifications.log().count() == 0);
+    }
+}
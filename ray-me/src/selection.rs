@@ -0,0 +1,364 @@
+//! Selecting segs, vertices and things on the canvas, and moving
+//! or deleting what is selected. A vertex is any position where
+//! seg ends meet, so moving one moves the end of every seg
+//! connected to it.
+
+use core::level_geometry::document::Thing;
+use core::level_geometry::geometry::{Line, Seg};
+use notan::math::{Vec2, Vec3Swizzles};
+use std::collections::BTreeSet;
+
+/// How close two positions have to be to count as the same vertex.
+const VERTEX_EPSILON: f32 = 1e-3;
+
+/// Items taken out of a list, along with the indices they were at.
+pub type Removed<T> = Vec<(usize, T)>;
+
+/// Items that were moved, as they were before and after, along
+/// with their indices.
+pub type Moved<T> = (Vec<(usize, T)>, Vec<(usize, T)>);
+
+/// What is under the cursor.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Hit {
+    Vertex(Vec2),
+    Thing(usize),
+    Seg(usize),
+}
+
+/// The selected segs and things, by index, and vertices, by
+/// position.
+#[derive(Clone, Default, Debug, PartialEq)]
+pub struct Selection {
+    pub segs: BTreeSet<usize>,
+    pub vertices: Vec<Vec2>,
+    pub things: BTreeSet<usize>,
+}
+impl Selection {
+    pub fn is_empty(&self) -> bool {
+        self.segs.is_empty() && self.vertices.is_empty() && self.things.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.segs.clear();
+        self.vertices.clear();
+        self.things.clear();
+    }
+
+    /// Returns whether a hit is already selected.
+    pub fn contains(&self, hit: Hit) -> bool {
+        match hit {
+            Hit::Seg(index) => self.segs.contains(&index),
+            Hit::Thing(index) => self.things.contains(&index),
+            Hit::Vertex(position) => self.contains_vertex(position),
+        }
+    }
+
+    /// Returns whether the vertex at a position is selected.
+    pub fn contains_vertex(&self, position: Vec2) -> bool {
+        self.vertices.iter().any(|vertex| same_vertex(*vertex, position))
+    }
+
+    /// Add a hit to the selection.
+    pub fn insert(&mut self, hit: Hit) {
+        match hit {
+            Hit::Seg(index) => {
+                self.segs.insert(index);
+            }
+            Hit::Thing(index) => {
+                self.things.insert(index);
+            }
+            Hit::Vertex(position) if !self.contains_vertex(position) => self.vertices.push(position),
+            Hit::Vertex(_) => (),
+        }
+    }
+
+    /// Add a hit to the selection, or take it out if it is in already.
+    pub fn toggle(&mut self, hit: Hit) {
+        match (hit, self.contains(hit)) {
+            (Hit::Seg(index), true) => {
+                self.segs.remove(&index);
+            }
+            (Hit::Thing(index), true) => {
+                self.things.remove(&index);
+            }
+            (Hit::Vertex(position), true) => self.vertices.retain(|vertex| !same_vertex(*vertex, position)),
+            (_, false) => self.insert(hit),
+        }
+    }
+
+    /// Add everything in another selection.
+    pub fn extend(&mut self, other: Selection) {
+        self.segs.extend(other.segs);
+        self.things.extend(other.things);
+        for vertex in other.vertices {
+            self.insert(Hit::Vertex(vertex));
+        }
+    }
+}
+
+/// Find what is under a point. Vertices within `radius` win over
+/// things, things over segs, and the closest of each wins.
+pub fn hit_test(segs: &[Seg], things: &[Thing], point: Vec2, radius: f32) -> Option<Hit> {
+    let closest_vertex = segs
+        .iter()
+        .flat_map(|seg| [seg.points().0.xy(), seg.points().1.xy()])
+        .map(|vertex| (vertex, vertex.distance(point)))
+        .filter(|(_, distance)| *distance <= radius)
+        .min_by(|a, b| a.1.total_cmp(&b.1));
+    if let Some((vertex, _)) = closest_vertex {
+        return Some(Hit::Vertex(vertex));
+    }
+
+    let closest_thing = things
+        .iter()
+        .enumerate()
+        .map(|(index, thing)| (index, thing.pos.xy().distance(point)))
+        .filter(|(_, distance)| *distance <= radius)
+        .min_by(|a, b| a.1.total_cmp(&b.1));
+    if let Some((index, _)) = closest_thing {
+        return Some(Hit::Thing(index));
+    }
+
+    segs.iter()
+        .enumerate()
+        .map(|(index, seg)| (index, distance_to_seg(seg, point)))
+        .filter(|(_, distance)| *distance <= radius)
+        .min_by(|a, b| a.1.total_cmp(&b.1))
+        .map(|(index, _)| Hit::Seg(index))
+}
+
+/// Select every vertex and thing inside the rectangle between two
+/// corners, and every seg with both ends inside it.
+pub fn box_select(segs: &[Seg], things: &[Thing], corner_a: Vec2, corner_b: Vec2) -> Selection {
+    let (min, max) = (corner_a.min(corner_b), corner_a.max(corner_b));
+    let inside = |point: Vec2| point.cmpge(min).all() && point.cmple(max).all();
+
+    let mut selection = Selection::default();
+    for (index, seg) in segs.iter().enumerate() {
+        let (p1, p2) = (seg.points().0.xy(), seg.points().1.xy());
+        for point in [p1, p2] {
+            if inside(point) {
+                selection.insert(Hit::Vertex(point));
+            }
+        }
+        if inside(p1) && inside(p2) {
+            selection.insert(Hit::Seg(index));
+        }
+    }
+    for (index, thing) in things.iter().enumerate() {
+        if inside(thing.pos.xy()) {
+            selection.insert(Hit::Thing(index));
+        }
+    }
+    selection
+}
+
+/// A move of the selection, from where everything was when it
+/// started. Only what moves is copied, once, so each step of a
+/// drag touches the selection rather than the whole map.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Moving {
+    /// The segs that move, as they were, and which of their ends
+    /// move. Both ends of selected segs move, as does every seg end
+    /// on a selected vertex.
+    segs: Vec<(usize, Seg, bool, bool)>,
+    things: Vec<(usize, Thing)>,
+    vertices: Vec<Vec2>,
+}
+impl Moving {
+    pub fn new(segs: &[Seg], things: &[Thing], selection: &Selection) -> Self {
+        let segs = segs
+            .iter()
+            .enumerate()
+            .filter_map(|(index, seg)| {
+                let whole = selection.segs.contains(&index);
+                let first = whole || selection.contains_vertex(seg.points().0.xy());
+                let second = whole || selection.contains_vertex(seg.points().1.xy());
+                (first || second).then_some((index, *seg, first, second))
+            })
+            .collect();
+        let things = selection
+            .things
+            .iter()
+            .filter_map(|index| Some((*index, *things.get(*index)?)))
+            .collect();
+        Moving {
+            segs,
+            things,
+            vertices: selection.vertices.clone(),
+        }
+    }
+
+    /// Put everything `offset` away from where it started, giving
+    /// back the segs and things that changed.
+    pub fn move_to(
+        &self,
+        segs: &mut [Seg],
+        things: &mut [Thing],
+        selection: &mut Selection,
+        offset: Vec2,
+    ) -> (Moved<Seg>, Moved<Thing>) {
+        let offset3 = offset.extend(0.0);
+        let mut moved_segs = Moved::default();
+        for (index, start, first, second) in &self.segs {
+            let (mut p1, mut p2) = start.points();
+            if *first {
+                p1 += offset3;
+            }
+            if *second {
+                p2 += offset3;
+            }
+            let seg = start.with_points((p1, p2));
+            if segs[*index] != seg {
+                moved_segs.0.push((*index, segs[*index]));
+                moved_segs.1.push((*index, seg));
+                segs[*index] = seg;
+            }
+        }
+        let mut moved_things = Moved::default();
+        for (index, start) in &self.things {
+            let thing = Thing {
+                pos: start.pos + offset3,
+                ..*start
+            };
+            if things[*index] != thing {
+                moved_things.0.push((*index, things[*index]));
+                moved_things.1.push((*index, thing));
+                things[*index] = thing;
+            }
+        }
+        selection.vertices = self.vertices.iter().map(|vertex| *vertex + offset).collect();
+        (moved_segs, moved_things)
+    }
+}
+
+/// Remove the selected segs and things and every seg touching a
+/// selected vertex, then clear the selection. Returns the removed
+/// segs and things along with the indices they were at.
+pub fn delete_selection(
+    segs: &mut Vec<Seg>,
+    things: &mut Vec<Thing>,
+    selection: &mut Selection,
+) -> (Removed<Seg>, Removed<Thing>) {
+    let mut removed = vec![];
+    let mut index = 0;
+    segs.retain(|seg| {
+        let remove = selection.segs.contains(&index)
+            || selection.contains_vertex(seg.points().0.xy())
+            || selection.contains_vertex(seg.points().1.xy());
+        if remove {
+            removed.push((index, *seg));
+        }
+        index += 1;
+        !remove
+    });
+    let mut removed_things = vec![];
+    for index in selection.things.iter().rev() {
+        removed_things.push((*index, things.remove(*index)));
+    }
+    removed_things.reverse();
+    selection.clear();
+    (removed, removed_things)
+}
+
+/// The shortest distance from a point to a seg in the xy plane.
+pub fn distance_to_seg(seg: &Seg, point: Vec2) -> f32 {
+    let (p1, p2) = (seg.points().0.xy(), seg.points().1.xy());
+    let along = p2 - p1;
+    let length_squared = along.length_squared();
+    if length_squared == 0.0 {
+        return point.distance(p1);
+    }
+    let t = ((point - p1).dot(along) / length_squared).clamp(0.0, 1.0);
+    point.distance(p1 + along * t)
+}
+
+fn same_vertex(a: Vec2, b: Vec2) -> bool {
+    a.distance(b) <= VERTEX_EPSILON
+}
+
+#[cfg(test)]
+pub mod tests {
+    use crate::selection::*;
+    use notan::math::Vec3;
+
+    /// A thing at (30, 30).
+    fn things() -> Vec<Thing> {
+        vec![Thing { pos: Vec3::new(30.0, 30.0, 0.0), ..Default::default() }]
+    }
+
+    /// Two segs meeting at (10, 0), and one on its own.
+    fn segs() -> Vec<Seg> {
+        let seg = |a: (f32, f32), b: (f32, f32)| Seg::new((Vec3::new(a.0, a.1, 0.0), Vec3::new(b.0, b.1, 0.0)), 100.0);
+        vec![
+            seg((0.0, 0.0), (10.0, 0.0)),
+            seg((10.0, 0.0), (10.0, 10.0)),
+            seg((50.0, 50.0), (60.0, 50.0)),
+        ]
+    }
+
+    #[test]
+    fn hits() {
+        let (segs, things) = (segs(), things());
+        assert!(hit_test(&segs, &things, Vec2::new(9.0, 1.0), 2.0) == Some(Hit::Vertex(Vec2::new(10.0, 0.0))));
+        assert!(hit_test(&segs, &things, Vec2::new(5.0, 1.0), 2.0) == Some(Hit::Seg(0)));
+        assert!(hit_test(&segs, &things, Vec2::new(31.0, 30.0), 2.0) == Some(Hit::Thing(0)));
+        assert!(hit_test(&segs, &things, Vec2::new(40.0, 40.0), 2.0).is_none());
+    }
+
+    #[test]
+    fn toggle() {
+        let mut selection = Selection::default();
+        selection.toggle(Hit::Seg(1));
+        selection.toggle(Hit::Vertex(Vec2::new(1.0, 1.0)));
+        assert!(selection.contains(Hit::Seg(1)) && selection.contains_vertex(Vec2::new(1.0, 1.0)));
+        selection.toggle(Hit::Seg(1));
+        selection.toggle(Hit::Vertex(Vec2::new(1.0, 1.0)));
+        assert!(selection.is_empty());
+    }
+
+    #[test]
+    fn box_selection() {
+        let selection = box_select(&segs(), &things(), Vec2::new(12.0, 12.0), Vec2::new(-1.0, -1.0));
+        assert!(selection.segs == BTreeSet::from([0, 1]));
+        assert!(selection.vertices.len() == 3 && selection.things.is_empty());
+
+        let selection = box_select(&segs(), &things(), Vec2::new(20.0, 20.0), Vec2::new(40.0, 40.0));
+        assert!(selection.things == BTreeSet::from([0]) && selection.segs.is_empty());
+    }
+
+    #[test]
+    fn move_vertex_moves_connected_segs() {
+        let (mut segs, mut things) = (segs(), things());
+        let mut selection = Selection::default();
+        selection.insert(Hit::Vertex(Vec2::new(10.0, 0.0)));
+        selection.insert(Hit::Thing(0));
+        let moving = Moving::new(&segs, &things, &selection);
+        moving.move_to(&mut segs, &mut things, &mut selection, Vec2::new(0.0, -2.0));
+        let (moved, moved_things) = moving.move_to(&mut segs, &mut things, &mut selection, Vec2::new(0.0, -5.0));
+
+        assert!(segs[0].points().1 == Vec3::new(10.0, -5.0, 0.0));
+        assert!(segs[1].points().0 == Vec3::new(10.0, -5.0, 0.0));
+        assert!(segs[0].points().0 == Vec3::ZERO);
+        assert!(selection.contains_vertex(Vec2::new(10.0, -5.0)) && selection.vertices.len() == 1);
+        assert!(things[0].pos == Vec3::new(30.0, 25.0, 0.0));
+
+        // Only what moved is given back, from where the last step left it
+        assert!(moved.0.iter().map(|(index, _)| *index).collect::<Vec<_>>() == vec![0, 1]);
+        assert!(moved.0[0].1.points().1 == Vec3::new(10.0, -2.0, 0.0));
+        assert!(moved_things.1 == vec![(0, things[0])]);
+    }
+
+    #[test]
+    fn delete() {
+        let (mut segs, mut things) = (segs(), things());
+        let mut selection = Selection::default();
+        selection.insert(Hit::Vertex(Vec2::new(10.0, 0.0)));
+        selection.insert(Hit::Thing(0));
+        let (removed, removed_things) = delete_selection(&mut segs, &mut things, &mut selection);
+        assert!(removed.iter().map(|(index, _)| *index).collect::<Vec<_>>() == vec![0, 1]);
+        assert!(removed_things.len() == 1 && things.is_empty());
+        assert!(segs.len() == 1 && selection.is_empty());
+    }
+}
@@ -0,0 +1,104 @@
+//! Snapping of points placed on the canvas to the grid and to
+//! existing geometry, and constraining lines to fixed angles.
+
+use core::level_geometry::document::{EditorSettings, MAX_GRID_SIZE, MIN_GRID_SIZE};
+use core::level_geometry::geometry::{Line, Seg};
+use notan::math::{Vec2, Vec3Swizzles};
+use std::f32::consts::FRAC_PI_4;
+
+/// The next power of two above the grid size, up to the largest
+/// size.
+pub fn grid_step_up(size: f32) -> f32 {
+    2f32.powf(size.log2().floor() + 1.0).clamp(MIN_GRID_SIZE, MAX_GRID_SIZE)
+}
+
+/// The next power of two below the grid size, down to the
+/// smallest size.
+pub fn grid_step_down(size: f32) -> f32 {
+    2f32.powf(size.log2().ceil() - 1.0).clamp(MIN_GRID_SIZE, MAX_GRID_SIZE)
+}
+
+/// Round a point to the nearest grid intersection.
+pub fn snap_to_grid(point: Vec2, size: f32) -> Vec2 {
+    (point / size).round() * size
+}
+
+/// Snap a point following the settings. Vertices and midpoints
+/// within `radius` win over the grid, closest first.
+pub fn snap(point: Vec2, settings: &EditorSettings, segs: &[Seg], radius: f32) -> Vec2 {
+    let mut targets = vec![];
+    for seg in segs {
+        let (p1, p2) = (seg.points().0.xy(), seg.points().1.xy());
+        if settings.snap_to_vertices {
+            targets.extend([p1, p2]);
+        }
+        if settings.snap_to_midpoints {
+            targets.push((p1 + p2) / 2.0);
+        }
+    }
+    let nearest = targets
+        .into_iter()
+        .map(|target| (target, target.distance(point)))
+        .filter(|(_, distance)| *distance <= radius)
+        .min_by(|a, b| a.1.total_cmp(&b.1));
+
+    match nearest {
+        Some((target, _)) => target,
+        None if settings.snap_to_grid => snap_to_grid(point, settings.grid_size),
+        None => point,
+    }
+}
+
+/// Turn the line from `from` to `to` to the nearest multiple of
+/// 45 degrees, keeping the length of `to` along that direction.
+pub fn constrain_angle(from: Vec2, to: Vec2) -> Vec2 {
+    let offset = to - from;
+    if offset == Vec2::ZERO {
+        return to;
+    }
+    let angle = (offset.y.atan2(offset.x) / FRAC_PI_4).round() * FRAC_PI_4;
+    let direction = Vec2::new(angle.cos(), angle.sin());
+    from + direction * offset.dot(direction)
+}
+
+#[cfg(test)]
+pub mod tests {
+    use crate::snapping::*;
+    use notan::math::Vec3;
+
+    fn segs() -> Vec<Seg> {
+        vec![Seg::new((Vec3::new(10.0, 10.0, 0.0), Vec3::new(50.0, 10.0, 0.0)), 100.0)]
+    }
+
+    #[test]
+    fn grid() {
+        assert!(grid_step_up(32.0) == 64.0 && grid_step_up(MAX_GRID_SIZE) == MAX_GRID_SIZE);
+        assert!(grid_step_down(32.0) == 16.0 && grid_step_down(MIN_GRID_SIZE) == MIN_GRID_SIZE);
+        // Sizes in between go to the powers of two either side
+        assert!(grid_step_up(48.0) == 64.0 && grid_step_down(48.0) == 32.0);
+        assert!(snap_to_grid(Vec2::new(17.0, 46.0), 32.0) == Vec2::new(32.0, 32.0));
+    }
+
+    #[test]
+    fn snap_priority() {
+        let mut settings = EditorSettings::default();
+        // A vertex in range beats the grid
+        assert!(snap(Vec2::new(12.0, 13.0), &settings, &segs(), 5.0) == Vec2::new(10.0, 10.0));
+        assert!(snap(Vec2::new(20.0, 13.0), &settings, &segs(), 5.0) == Vec2::new(32.0, 0.0));
+
+        settings.snap_to_midpoints = true;
+        assert!(snap(Vec2::new(29.0, 12.0), &settings, &segs(), 5.0) == Vec2::new(30.0, 10.0));
+
+        settings.snap_to_grid = false;
+        assert!(snap(Vec2::new(70.0, 70.0), &settings, &segs(), 5.0) == Vec2::new(70.0, 70.0));
+    }
+
+    #[test]
+    fn angles() {
+        let from = Vec2::new(0.0, 0.0);
+        assert!(constrain_angle(from, Vec2::new(10.0, 1.0)) == Vec2::new(10.0, 0.0));
+        let diagonal = constrain_angle(from, Vec2::new(10.0, 9.0));
+        assert!((diagonal.x - diagonal.y).abs() < 1e-4 && (diagonal.x - 9.5).abs() < 1e-4);
+        assert!(constrain_angle(from, from) == from);
+    }
+}
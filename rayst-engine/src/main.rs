@@ -1,12 +1,25 @@
+use std::collections::BTreeMap;
 use std::default::Default;
+use std::env;
 use std::f32::consts::PI;
 
+use notan::log;
 use notan::prelude::*;
 use notan::math::{Mat4, Vec3};
+use core::level_geometry::document::MapDocument;
+use core::textures::animation::TextureAnimator;
+use core::textures::atlas::{self, Atlas, AtlasOptions};
+use core::textures::gpu;
+use core::textures::image_data::ImageData;
+use core::textures::lighting::LightRamp;
+use core::textures::manifest::{FilterMode, TextureManifest};
 
 mod weapons;
 mod entities;
 mod animation;
+mod mesh;
+
+use crate::mesh::WallMesh;
 
 use crate::weapons::*;
 use crate::entities::*;
@@ -18,20 +31,32 @@ const ASPECT_RATIO: f32 = 16.0/9.0;
 const Z_NEAR: f32 = 0.0;
 const Z_FAR: f32 = 255.0; 
 
+// Atlas pages that are mipmapped get a wider bleed, so that the
+// smaller levels the driver makes take longer to mix in the
+// textures next to each other
+const MIP_BLEED: u32 = 8;
+
 // notan example shaders
 // takes in the position and colour like a sliding window
 // over the array of values passed in. It uses a uniform buffer
 // Locals declared later to pass in a modelview projection.
 // It does not alter the colour, but it does perform matrix transformations
-// on the gpu which is a lot faster than on the cpu.
+// on the gpu which is a lot faster than on the cpu. The sector light level
+// and the distance from the camera are passed on for the lighting, and
+// the UV is already on the wall's atlas page.
 //language=glsl
 const VERT: ShaderSource = notan::vertex_shader! {
     r#"
     #version 450
     layout(location = 0) in vec4 a_position;
     layout(location = 1) in vec4 a_color;
+    layout(location = 2) in float a_light;
+    layout(location = 3) in vec2 a_uv;
 
     layout(location = 0) out vec4 v_color;
+    layout(location = 1) out float v_light;
+    layout(location = 2) out float v_distance;
+    layout(location = 3) out vec2 v_uv;
 
     layout(set = 0, binding = 0) uniform Locals {
         mat4 u_matrix;
@@ -39,13 +64,18 @@ const VERT: ShaderSource = notan::vertex_shader! {
 
     void main() {
         v_color = a_color;
+        v_light = a_light;
+        v_uv = a_uv;
         gl_Position = u_matrix * a_position;
+        v_distance = gl_Position.w;
     }
     "#
 };
 
-// Another notan example. Fairly self explanatory boilerplate, you
-// don't usually get much different to this.
+// Picks a light level the same way as `LightRamp::level` does on the
+// cpu, then darkens the texel from the atlas page by that level's
+// brightness from the light ramp strip. Fullbright walls have a
+// light level above 255 and are not darkened at all.
 //language=glsl
 const FRAG: ShaderSource = notan::fragment_shader! {
     r#"
@@ -53,24 +83,55 @@ const FRAG: ShaderSource = notan::fragment_shader! {
     precision mediump float;
 
     layout(location = 0) in vec4 v_color;
+    layout(location = 1) in float v_light;
+    layout(location = 2) in float v_distance;
+    layout(location = 3) in vec2 v_uv;
     layout(location = 0) out vec4 color;
 
+    layout(binding = 0) uniform sampler2D u_light_ramp;
+    layout(binding = 1) uniform sampler2D u_texture;
+
+    layout(set = 0, binding = 1) uniform Lighting {
+        float u_distance_per_level;
+    };
+
     void main() {
-        color = v_color;
+        float levels = float(textureSize(u_light_ramp, 0).x);
+        float sector_darkness = (255.0 - v_light) / 256.0 * levels;
+        float distance_darkness = max(v_distance, 0.0) / u_distance_per_level;
+        float level = min(floor(sector_darkness + distance_darkness), levels - 1.0);
+        float brightness = texture(u_light_ramp, vec2((level + 0.5) / levels, 0.5)).r;
+        if (v_light > 255.0) {
+            brightness = 1.0;
+        }
+        vec4 texel = texture(u_texture, v_uv) * v_color;
+        color = vec4(texel.rgb * brightness, texel.a);
     }
     "#
 };
 
+#[derive(AppState)]
 struct State {
     clear_options: ClearOptions,
     pipeline: Pipeline,
     vbo: Buffer, // Vertex Buffer
     ibo: Buffer, // Index Buffer
     ubo: Buffer, // Uniform Buffer
+    lighting_ubo: Buffer, // The light falloff, for the Lighting block
     mvp: notan::math::Mat4, // Model View Projection Matrix
     fov: f32,
     player: Player,
     animation_max_fps: usize,
+    texture_animator: TextureAnimator,
+    light_ramp: LightRamp,
+    light_ramp_texture: Texture,
+    document: MapDocument,
+    manifest: TextureManifest,
+    atlas: Atlas,
+    atlas_pages: Vec<Texture>,
+    mesh: WallMesh,
+    /// The animator time that the mesh was last built at.
+    mesh_time: f32,
 }
 
 impl State {
@@ -93,26 +154,224 @@ impl State {
             compare: CompareMode::Less
         };
 
-        let vertex_info = VertexInfo::new()
-            .attr(0, VertexFormat::Float32x3) // Position
-            .attr(1, VertexFormat::Float32x4); // Colour
+        let vertex_info = vertex_info();
 
         let pipeline = gfx.create_pipeline()
             .from(&VERT, &FRAG)
             .with_vertex_info(&vertex_info)
             .with_depth_stencil(stencil)
+            .with_texture_location(0, "u_light_ramp")
+            .with_texture_location(1, "u_texture")
             .build()
             .unwrap();
 
-        // I need to make something to unwrap verts and idcs from map data
-        let vertices = todo!();
-        let indices = todo!();
+        let light_ramp = LightRamp::default();
+        let light_ramp_texture = gpu::upload(gfx, &light_ramp.to_image()).unwrap();
+        let lighting_ubo = gfx.create_uniform_buffer(1, "Lighting")
+            .with_data(&lighting(&light_ramp))
+            .build()
+            .unwrap();
+
+        // The map and the texture manifest are given on the command line
+        let mut args = env::args().skip(1);
+        let document = match args.next().map(MapDocument::load) {
+            Some(Ok(document)) => document,
+            Some(Err(e)) => {
+                log::error!("Failed to load the map, starting empty: {e:#}");
+                MapDocument::new()
+            }
+            None => MapDocument::new(),
+        };
+        let manifest = match args.next().map(TextureManifest::load) {
+            Some(Ok(manifest)) => manifest,
+            Some(Err(e)) => {
+                log::error!("Failed to load the texture manifest: {e:#}");
+                TextureManifest::default()
+            }
+            None => TextureManifest::default(),
+        };
+        let (atlas, filters) = pack_atlas(&manifest);
+        let atlas_pages = atlas
+            .pages
+            .iter()
+            .zip(filters)
+            .map(|(page, filter)| gpu::upload_filtered(gfx, page, filter).unwrap())
+            .collect();
+
+        let texture_animator = TextureAnimator::from_manifest(&manifest);
+        let mesh = mesh::build_walls(&document, &atlas, &texture_animator, &manifest);
+        let vbo = gfx.create_vertex_buffer()
+            .with_info(&vertex_info)
+            .with_data(&mesh.vertices)
+            .build()
+            .unwrap();
+        let ibo = gfx.create_index_buffer()
+            .with_data(&mesh.indices)
+            .build()
+            .unwrap();
 
         let projection = Mat4::perspective_rh_gl(FOV, ASPECT_RATIO, Z_NEAR, Z_FAR);
+        let mvp = projection * view(&player);
+        let ubo = gfx.create_uniform_buffer(0, "Locals")
+            .with_data(&mvp)
+            .build()
+            .unwrap();
+
+        State {
+            clear_options,
+            pipeline,
+            vbo,
+            ibo,
+            ubo,
+            lighting_ubo,
+            mvp,
+            fov: FOV,
+            player,
+            animation_max_fps: 35,
+            texture_animator,
+            light_ramp,
+            light_ramp_texture,
+            document,
+            manifest,
+            atlas,
+            atlas_pages,
+            mesh,
+            mesh_time: 0.0,
+        }
+    }
+}
+
+/// The contents of the Lighting uniform block, padded out to the
+/// 16 bytes that std140 gives a block with one float in it.
+fn lighting(light_ramp: &LightRamp) -> [f32; 4] {
+    [light_ramp.falloff.distance_per_level, 0.0, 0.0, 0.0]
+}
+
+/// The layout of the vertices in a `WallMesh`.
+fn vertex_info() -> VertexInfo {
+    VertexInfo::new()
+        .attr(0, VertexFormat::Float32x3) // Position
+        .attr(1, VertexFormat::Float32x4) // Colour
+        .attr(2, VertexFormat::Float32) // Sector light level
+        .attr(3, VertexFormat::Float32x2) // UV on the atlas page
+}
+
+/// Pack every texture in the manifest into an atlas, along with the
+/// missing texture that walls fall back to. Textures with different
+/// filtering go on different pages, so that each page can be
+/// uploaded with its own. Returns the atlas and the filtering of each
+/// page. Textures that fail to load are logged and left out.
+fn pack_atlas(manifest: &TextureManifest) -> (Atlas, Vec<FilterMode>) {
+    let mut atlas = Atlas {
+        pages: vec![],
+        regions: BTreeMap::new(),
+    };
+    let mut filters = vec![];
+    for filter in [FilterMode::Nearest, FilterMode::Linear, FilterMode::Trilinear] {
+        let mut images = vec![];
+        if filter == FilterMode::default() {
+            images.push(ImageData::missing());
+        }
+        for entry in manifest.textures.iter().filter(|entry| entry.filter == filter) {
+            match manifest.load_image(entry) {
+                Ok(image) => images.push(image),
+                Err(e) => log::error!("Skipping texture {}: {e}", entry.name),
+            }
+        }
+        let options = match filter.uses_mipmaps() {
+            true => AtlasOptions { bleed: MIP_BLEED, ..Default::default() },
+            false => AtlasOptions::default(),
+        };
+        let packed = match atlas::pack(&images, &options) {
+            Ok(packed) => packed,
+            Err(e) => {
+                log::error!("Failed to pack the {filter:?} textures, leaving them out: {e}");
+                // Walls still need something to fall back to
+                let missing = usize::from(filter == FilterMode::default());
+                atlas::pack(&images[..missing], &options).unwrap()
+            }
+        };
+
+        let first = atlas.pages.len();
+        for (name, mut region) in packed.regions {
+            region.page += first;
+            atlas.regions.insert(name, region);
+        }
+        filters.resize(filters.len() + packed.pages.len(), filter);
+        atlas.pages.extend(packed.pages);
     }
+    (atlas, filters)
+}
+
+/// The view matrix from the player's eyes. The map is z up.
+fn view(player: &Player) -> Mat4 {
+    let angle = player.angle_deg.to_radians();
+    let facing = Vec3::new(angle.cos(), angle.sin(), 0.0);
+    Mat4::look_at_rh(player.pos, player.pos + facing, Vec3::Z)
+}
+
+/// Build the walls again for the current frame of every animated
+/// texture, at most `animation_max_fps` times a second.
+fn animate_walls(gfx: &mut Graphics, state: &mut State) {
+    let time = state.texture_animator.time();
+    if state.texture_animator.is_empty() || time - state.mesh_time < 1.0 / state.animation_max_fps as f32 {
+        return;
+    }
+    state.mesh_time = time;
+    let mesh = mesh::build_walls(&state.document, &state.atlas, &state.texture_animator, &state.manifest);
+    if mesh == state.mesh {
+        return;
+    }
+
+    // Frames of different sizes can change how many quads there are,
+    // and frames on other atlas pages change which batch walls are
+    // in, so the indices are written again along with the vertices
+    match mesh.indices.len() == state.mesh.indices.len() {
+        true => {
+            gfx.set_buffer_data(&state.vbo, &mesh.vertices);
+            gfx.set_buffer_data(&state.ibo, &mesh.indices);
+        }
+        false => {
+            state.vbo = gfx.create_vertex_buffer()
+                .with_info(&vertex_info())
+                .with_data(&mesh.vertices)
+                .build()
+                .unwrap();
+            state.ibo = gfx.create_index_buffer()
+                .with_data(&mesh.indices)
+                .build()
+                .unwrap();
+        }
+    }
+    state.mesh = mesh;
+}
+
+/// Draw every wall, one batch per atlas page.
+fn draw(gfx: &mut Graphics, state: &mut State) {
+    animate_walls(gfx, state);
+    gfx.set_buffer_data(&state.lighting_ubo, &lighting(&state.light_ramp));
+    let mut renderer = gfx.create_renderer();
+    renderer.begin(Some(state.clear_options));
+    renderer.set_pipeline(&state.pipeline);
+    renderer.bind_buffers(&[&state.vbo, &state.ibo, &state.ubo, &state.lighting_ubo]);
+    renderer.bind_texture(0, &state.light_ramp_texture);
+    for batch in &state.mesh.batches {
+        renderer.bind_texture(1, &state.atlas_pages[batch.page]);
+        renderer.draw(batch.start as i32, batch.count as i32);
+    }
+    renderer.end();
+    gfx.render(&renderer);
+}
+
+/// Advance everything that depends on the game time by one tick.
+fn update(app: &mut App, state: &mut State) {
+    state.texture_animator.tick(app.timer.delta_f32());
 }
 
 #[notan_main]
-fn main() {
-    println!("Hello, world!");
+fn main() -> Result<(), String> {
+    notan::init_with(State::init)
+        .update(update)
+        .draw(draw)
+        .build()
 }
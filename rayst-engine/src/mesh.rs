@@ -0,0 +1,330 @@
+//! Turning the walls of a map into vertices and indices for the
+//! gpu. Every wall is textured from the atlas, so the UVs of each
+//! wall are remapped onto its atlas page, and walls are batched by
+//! page so each page only has to be bound once.
+
+use core::level_geometry::document::MapDocument;
+use core::level_geometry::geometry::{Line, Seg};
+use core::textures::animation::TextureAnimator;
+use core::textures::atlas::{Atlas, AtlasRegion};
+use core::textures::manifest::TextureManifest;
+use core::textures::MISSING_TEX_NAME;
+use notan::math::{Vec2, Vec3};
+use std::collections::BTreeMap;
+
+/// The number of floats in each vertex: a position, a colour, the
+/// sector light level and a UV.
+pub const VERTEX_FLOATS: usize = 10;
+
+/// The most copies of a texture along either side of a wall.
+const MAX_REPEATS: usize = 1024;
+
+/// The light level of fullbright walls. It is above any sector
+/// light level, and the shader draws it without any darkening.
+pub const FULLBRIGHT: f32 = 256.0;
+
+/// A run of indices that are all drawn from one atlas page.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Batch {
+    pub page: usize,
+    pub start: u32,
+    pub count: u32,
+}
+
+/// The vertices and indices of every wall in a map.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct WallMesh {
+    pub vertices: Vec<f32>,
+    pub indices: Vec<u32>,
+    pub batches: Vec<Batch>,
+}
+
+/// A piece of a wall that one copy of a texture covers, along
+/// one of its axes.
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Span {
+    /// Distance along the wall where the piece starts and ends.
+    from: f32,
+    to: f32,
+    /// Where the piece starts and ends in the texture, from 0 to 1.
+    uv_from: f32,
+    uv_to: f32,
+}
+
+/// Build the mesh for every wall in a map. Textures are the size
+/// given by their scale in the manifest and repeat along the wall,
+/// so a wall is split into a quad for every copy of its texture,
+/// because UVs on an atlas page cannot wrap. Animated textures are
+/// drawn with their frame and scroll at the animator's current time.
+/// Walls with no texture, or one that is not in the atlas, use the
+/// missing texture.
+pub fn build_walls(
+    document: &MapDocument,
+    atlas: &Atlas,
+    animator: &TextureAnimator,
+    manifest: &TextureManifest,
+) -> WallMesh {
+    let mut pages: BTreeMap<usize, Vec<u32>> = BTreeMap::new();
+    let mut vertices = vec![];
+
+    for seg in &document.geometry.segs {
+        let texture = seg.texture();
+        let resolved = animator.resolve(texture.as_str());
+        let region = match atlas.region(resolved.texture).or(atlas.region(MISSING_TEX_NAME)) {
+            Some(region) => region,
+            None => continue,
+        };
+        let scroll = Vec2::from(resolved.offset) * Vec2::new(region.width as f32, region.height as f32);
+        // Emissive walls are as bright as they can be in any sector,
+        // but still fade with distance unless they are fullbright
+        let entry = manifest.get(resolved.texture);
+        let light = match entry {
+            Some(entry) if entry.fullbright => FULLBRIGHT,
+            Some(entry) if entry.emissive => 255.0,
+            _ => document.light_level(seg) as f32,
+        };
+        let scale = entry.map(|entry| entry.scale).filter(|scale| *scale > 0.0).unwrap_or(1.0);
+        let indices = pages.entry(region.page).or_default();
+        wall_quads(seg, region, scroll, scale, light, &mut vertices, indices);
+    }
+
+    let mut mesh = WallMesh {
+        vertices,
+        ..Default::default()
+    };
+    for (page, indices) in pages {
+        mesh.batches.push(Batch {
+            page,
+            start: mesh.indices.len() as u32,
+            count: indices.len() as u32,
+        });
+        mesh.indices.extend(indices);
+    }
+    mesh
+}
+
+/// Add a quad for every copy of the texture on a wall, with the
+/// texture shifted a further `scroll` pixels, `scale` world units
+/// to a pixel and lit at `light`.
+fn wall_quads(
+    seg: &Seg,
+    region: &AtlasRegion,
+    scroll: Vec2,
+    scale: f32,
+    light: f32,
+    vertices: &mut Vec<f32>,
+    indices: &mut Vec<u32>,
+) {
+    let (start, end) = seg.points();
+    let length = start.distance(end);
+    let offset = seg.texture_offset() + scroll;
+    let up = Vec3::Z * seg.height();
+
+    // Textures hang down from the top of the wall. Spans are in
+    // pixels of the texture, and scaled back up to world units.
+    for across in spans(offset.x, length / scale, region.width as f32) {
+        for down in spans(offset.y, seg.height() / scale, region.height as f32) {
+            let first = (vertices.len() / VERTEX_FLOATS) as u32;
+            let corners = [
+                (across.from, down.from, across.uv_from, down.uv_from),
+                (across.to, down.from, across.uv_to, down.uv_from),
+                (across.to, down.to, across.uv_to, down.uv_to),
+                (across.from, down.to, across.uv_from, down.uv_to),
+            ];
+            for (along, below_top, u, v) in corners {
+                let position = start.lerp(end, along * scale / length) + up - Vec3::Z * below_top * scale;
+                let (u, v) = region.remap_uv((u, v));
+                vertices.extend([position.x, position.y, position.z]);
+                vertices.extend([1.0, 1.0, 1.0, 1.0]);
+                vertices.push(light);
+                vertices.extend([u, v]);
+            }
+            indices.extend([first, first + 1, first + 2, first, first + 2, first + 3]);
+        }
+    }
+}
+
+/// Split a wall `length` long into the pieces that each copy of a
+/// texture `size` pixels long covers, starting `offset` pixels
+/// into the texture. Walls that would need more than `MAX_REPEATS`
+/// copies have the texture stretched to that many.
+fn spans(offset: f32, length: f32, size: f32) -> Vec<Span> {
+    if size <= 0.0 || !length.is_finite() || !offset.is_finite() {
+        return vec![];
+    }
+    let size = size.max(length / MAX_REPEATS as f32);
+    // `rem_euclid` can round up to `size` itself
+    let texel = match offset.rem_euclid(size) {
+        texel if texel < size => texel,
+        _ => 0.0,
+    };
+    let count = ((length + texel) / size).ceil() as usize;
+    (0..count)
+        .filter_map(|copy| {
+            // Where this copy of the texture starts along the wall
+            let start = copy as f32 * size - texel;
+            let (from, to) = (start.max(0.0), (start + size).min(length));
+            (from < to).then_some(Span {
+                from,
+                to,
+                uv_from: (from - start) / size,
+                uv_to: (to - start) / size,
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+pub mod tests {
+    use crate::mesh::*;
+    use core::level_geometry::document::Sector;
+    use core::level_geometry::geometry::TextureName;
+    use core::textures::animation::{AnimatedTexture, AnimationFrame};
+    use core::textures::atlas::{self, AtlasOptions};
+    use core::textures::image_data::ImageData;
+
+    fn atlas() -> Atlas {
+        let images = [
+            ImageData::filled("STARTAN3", 64, 32, [0; 4]).unwrap(),
+            ImageData::filled(MISSING_TEX_NAME, 8, 8, [0; 4]).unwrap(),
+        ];
+        atlas::pack(&images, &AtlasOptions::default()).unwrap()
+    }
+
+    /// Build walls with no animations and no manifest.
+    fn plain_walls(document: &MapDocument, atlas: &Atlas) -> WallMesh {
+        build_walls(document, atlas, &TextureAnimator::default(), &TextureManifest::default())
+    }
+
+    #[test]
+    fn texture_spans() {
+        let spans = spans(16.0, 100.0, 64.0);
+        assert!(spans.len() == 2);
+        assert!(spans[0] == Span { from: 0.0, to: 48.0, uv_from: 0.25, uv_to: 1.0 });
+        assert!(spans[1] == Span { from: 48.0, to: 100.0, uv_from: 0.0, uv_to: 52.0 / 64.0 });
+        assert!(super::spans(0.0, 0.0, 64.0).is_empty());
+
+        // Far too long to tile one pixel at a time
+        let long = super::spans(0.0, 1e12, 1.0);
+        assert!(long.len() == MAX_REPEATS && long.last().unwrap().to == 1e12);
+        assert!(super::spans(0.0, f32::INFINITY, 64.0).is_empty());
+    }
+
+    #[test]
+    fn uvs_are_on_the_atlas_page() {
+        let texture = TextureName::new("STARTAN3").unwrap();
+        let seg = Seg::new((Vec3::ZERO, Vec3::new(64.0, 0.0, 0.0)), 32.0).with_texture(texture);
+        let atlas = atlas();
+        let mesh = plain_walls(&MapDocument::from_segs(vec![seg]), &atlas);
+
+        let region = atlas.region("STARTAN3").unwrap();
+        assert!(mesh.vertices.len() == 4 * VERTEX_FLOATS);
+        assert!(mesh.indices.len() == 6);
+        assert!(mesh.batches == vec![Batch { page: region.page, start: 0, count: 6 }]);
+        // The first corner is the top left of the texture
+        assert!(mesh.vertices[2] == 32.0);
+        assert!((mesh.vertices[8], mesh.vertices[9]) == region.uv_min);
+    }
+
+    #[test]
+    fn sector_light() {
+        let seg = Seg::new((Vec3::ZERO, Vec3::new(8.0, 0.0, 0.0)), 8.0);
+        let mut document = MapDocument::from_segs(vec![seg, seg.with_sector(Some(0))]);
+        document.geometry.sectors.push(Sector {
+            light_level: 96,
+            ..Default::default()
+        });
+        let mesh = plain_walls(&document, &atlas());
+        let lights: Vec<f32> = mesh.vertices.chunks_exact(VERTEX_FLOATS).map(|vertex| vertex[7]).collect();
+        assert!(lights == [[255.0; 4], [96.0; 4]].concat());
+    }
+
+    #[test]
+    fn manifest_properties() {
+        let json = r#"{ "textures": [
+            { "name": "STARTAN3", "path": "a.png", "scale": 2.0, "fullbright": true }
+        ] }"#;
+        let manifest = TextureManifest::from_json(json, "").unwrap();
+        let texture = TextureName::new("STARTAN3").unwrap();
+        let seg = Seg::new((Vec3::ZERO, Vec3::new(256.0, 0.0, 0.0)), 64.0).with_texture(texture);
+        let mesh = build_walls(&MapDocument::from_segs(vec![seg]), &atlas(), &TextureAnimator::default(), &manifest);
+
+        // 64 pixels at 2 units each covers 128 units, so two copies
+        assert!(mesh.indices.len() == 12);
+        assert!(mesh.vertices[VERTEX_FLOATS] == 128.0);
+        assert!(mesh.vertices[7] == FULLBRIGHT);
+
+        let json = json.replace("fullbright", "emissive");
+        let manifest = TextureManifest::from_json(&json, "").unwrap();
+        let mut document = MapDocument::from_segs(vec![seg.with_sector(Some(0))]);
+        document.geometry.sectors.push(Sector {
+            light_level: 96,
+            ..Default::default()
+        });
+        let mesh = build_walls(&document, &atlas(), &TextureAnimator::default(), &manifest);
+        assert!(mesh.vertices[7] == 255.0);
+    }
+
+    #[test]
+    fn missing_textures() {
+        let seg = Seg::new((Vec3::ZERO, Vec3::new(16.0, 0.0, 0.0)), 8.0);
+        let mesh = plain_walls(&MapDocument::from_segs(vec![seg]), &atlas());
+        // Two copies of the 8 pixel missing texture across the wall
+        assert!(mesh.indices.len() == 12);
+        let empty = Atlas {
+            pages: vec![],
+            regions: BTreeMap::new(),
+        };
+        assert!(plain_walls(&MapDocument::from_segs(vec![seg]), &empty).indices.is_empty());
+    }
+
+    #[test]
+    fn animated_textures() {
+        let texture = TextureName::new("NUKAGE1").unwrap();
+        let seg = Seg::new((Vec3::ZERO, Vec3::new(64.0, 0.0, 0.0)), 32.0).with_texture(texture);
+        let mut animator = TextureAnimator::default();
+        animator.insert("NUKAGE1", AnimatedTexture {
+            frames: vec![AnimationFrame { texture: TextureName::new("STARTAN3").unwrap(), duration: 1.0 }],
+            scroll: [0.5, 0.0],
+        });
+        animator.tick(1.0);
+        let atlas = atlas();
+        let mesh = build_walls(&MapDocument::from_segs(vec![seg]), &atlas, &animator, &TextureManifest::default());
+
+        // Drawn with the frame, scrolled half way so it takes two quads
+        let region = atlas.region("STARTAN3").unwrap();
+        assert!(mesh.indices.len() == 12);
+        assert!(mesh.vertices[8] == region.remap_uv((0.5, 0.0)).0);
+    }
+
+    #[test]
+    fn frame_on_another_page() {
+        // Each texture gets a page to itself
+        let images = [
+            ImageData::filled("NUKAGE1", 8, 8, [0; 4]).unwrap(),
+            ImageData::filled("NUKAGE2", 8, 8, [0; 4]).unwrap(),
+        ];
+        let atlas = atlas::pack(&images, &AtlasOptions { page_size: 16, ..Default::default() }).unwrap();
+        let wall = |name| {
+            let texture = TextureName::new(name).unwrap();
+            Seg::new((Vec3::ZERO, Vec3::new(8.0, 0.0, 0.0)), 8.0).with_texture(texture)
+        };
+        let document = MapDocument::from_segs(vec![wall("NUKAGE2"), wall("NUKAGE1")]);
+        let mut animator = TextureAnimator::default();
+        animator.insert("NUKAGE1", AnimatedTexture {
+            frames: vec![
+                AnimationFrame { texture: TextureName::new("NUKAGE1").unwrap(), duration: 1.0 },
+                AnimationFrame { texture: TextureName::new("NUKAGE2").unwrap(), duration: 1.0 },
+            ],
+            scroll: [0.0; 2],
+        });
+        let before = build_walls(&document, &atlas, &animator, &TextureManifest::default());
+        animator.tick(1.0);
+        let after = build_walls(&document, &atlas, &animator, &TextureManifest::default());
+
+        // As many quads as before, but the indices are not the same
+        assert!(before.batches.len() == 2);
+        assert!(after.batches == vec![Batch { page: 1, start: 0, count: 12 }]);
+        assert!(after.indices.len() == before.indices.len() && after.indices != before.indices);
+    }
+}